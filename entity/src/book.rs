@@ -11,6 +11,15 @@ pub struct Model {
     pub hash: String,
     pub user_id: i32,
     pub file_tyoe: i32,
+    /// bytes, `None` for books uploaded before we kept track
+    pub size: Option<i64>,
+    /// unix timestamps
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// name of the file as it was uploaded
+    pub file_name: Option<String>,
+    /// KOReader's partial md5 of the file, what kosync calls the document
+    pub partial_md5: Option<String>,
     // pub email_id: i32,
 }
 
@@ -24,6 +33,8 @@ pub enum Relation {
     //     on_delete = "NoAction"
     // )]
     // Email,
+    #[sea_orm(has_many = "super::annotation::Entity")]
+    Annotation,
     #[sea_orm(
         belongs_to = "super::file_type::Entity",
         from = "Column::FileTyoe",
@@ -32,6 +43,24 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     FileType,
+    #[sea_orm(has_one = "super::book_override::Entity")]
+    BookOverride,
+    #[sea_orm(has_many = "super::delivery::Entity")]
+    Delivery,
+    #[sea_orm(has_many = "super::book_tag::Entity")]
+    BookTag,
+    #[sea_orm(has_many = "super::collection_book::Entity")]
+    CollectionBook,
+    #[sea_orm(has_many = "super::reading_progress::Entity")]
+    ReadingProgress,
+    #[sea_orm(has_many = "super::reading_session::Entity")]
+    ReadingSession,
+    #[sea_orm(has_many = "super::reading_status::Entity")]
+    ReadingStatus,
+    #[sea_orm(has_many = "super::share::Entity")]
+    Share,
+    #[sea_orm(has_many = "super::share_link::Entity")]
+    ShareLink,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::UserId",
@@ -48,12 +77,72 @@ pub enum Relation {
 //     }
 // }
 
+impl Related<super::annotation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Annotation.def()
+    }
+}
+
 impl Related<super::file_type::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::FileType.def()
     }
 }
 
+impl Related<super::book_override::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BookOverride.def()
+    }
+}
+
+impl Related<super::delivery::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Delivery.def()
+    }
+}
+
+impl Related<super::book_tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BookTag.def()
+    }
+}
+
+impl Related<super::collection_book::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CollectionBook.def()
+    }
+}
+
+impl Related<super::reading_progress::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingProgress.def()
+    }
+}
+
+impl Related<super::reading_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingSession.def()
+    }
+}
+
+impl Related<super::reading_status::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingStatus.def()
+    }
+}
+
+impl Related<super::share::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Share.def()
+    }
+}
+
+impl Related<super::share_link::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShareLink.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
@@ -1,9 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.6
 
 use sea_orm::entity::prelude::*;
-use serde::Serialize;
+use sea_orm::FromJsonQueryResult;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Serialize, Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "book_info")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -12,8 +13,30 @@ pub struct Model {
     pub title: String,
     pub creator: String,
     pub cover_mime: Option<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    pub published: Option<String>,
+    pub identifiers: Option<Identifiers>,
+    pub subjects: Option<Subjects>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Identifier {
+    /// `isbn`, `uuid`, ... - `None` if we couldn't tell
+    pub scheme: Option<String>,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, FromJsonQueryResult)]
+pub struct Identifiers(pub Vec<Identifier>);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, FromJsonQueryResult)]
+pub struct Subjects(pub Vec<String>);
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
@@ -1,14 +1,22 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.6
 
 use sea_orm::entity::prelude::*;
+use serde::Serialize;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Serialize, Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "email")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub email: String,
     pub user_id: i32,
+    /// unix timestamp, books only get sent to verified addresses
+    pub verified_at: Option<i64>,
+    #[serde(skip_serializing)]
+    pub verification_token: Option<String>,
+    /// unix timestamp after which `verification_token` stops working
+    #[serde(skip_serializing)]
+    pub verification_expires_at: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
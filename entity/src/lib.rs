@@ -2,8 +2,22 @@
 
 pub mod prelude;
 
+pub mod annotation;
 pub mod book;
+pub mod book_change;
 pub mod book_info;
+pub mod book_override;
+pub mod book_tag;
+pub mod collection;
+pub mod collection_book;
+pub mod delivery;
+pub mod device;
 pub mod email;
 pub mod file_type;
+pub mod reading_progress;
+pub mod reading_session;
+pub mod reading_status;
+pub mod share;
+pub mod share_link;
+pub mod tag;
 pub mod user;
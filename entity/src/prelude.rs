@@ -1,7 +1,21 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.6
 
+pub use super::annotation::Entity as Annotation;
 pub use super::book::Entity as Book;
+pub use super::book_change::Entity as BookChange;
 pub use super::book_info::Entity as BookInfo;
+pub use super::book_override::Entity as BookOverride;
+pub use super::book_tag::Entity as BookTag;
+pub use super::collection::Entity as Collection;
+pub use super::collection_book::Entity as CollectionBook;
+pub use super::delivery::Entity as Delivery;
+pub use super::device::Entity as Device;
 pub use super::email::Entity as Email;
 pub use super::file_type::Entity as FileType;
+pub use super::reading_progress::Entity as ReadingProgress;
+pub use super::reading_session::Entity as ReadingSession;
+pub use super::reading_status::Entity as ReadingStatus;
+pub use super::share::Entity as Share;
+pub use super::share_link::Entity as ShareLink;
+pub use super::tag::Entity as Tag;
 pub use super::user::Entity as User;
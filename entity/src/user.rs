@@ -10,14 +10,43 @@ pub struct Model {
     pub username: String,
     pub password: String,
     pub admin: bool,
+    /// argon2 hash of the md5 KOReader's sync plugin logs in with, `None` until a sync password is set
+    #[serde(skip_serializing)]
+    pub kosync_key: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::annotation::Entity")]
+    Annotation,
     #[sea_orm(has_many = "super::book::Entity")]
     Book,
+    #[sea_orm(has_many = "super::book_change::Entity")]
+    BookChange,
+    #[sea_orm(has_many = "super::collection::Entity")]
+    Collection,
+    #[sea_orm(has_many = "super::delivery::Entity")]
+    Delivery,
+    #[sea_orm(has_many = "super::device::Entity")]
+    Device,
     #[sea_orm(has_many = "super::email::Entity")]
     Email,
+    #[sea_orm(has_many = "super::reading_progress::Entity")]
+    ReadingProgress,
+    #[sea_orm(has_many = "super::reading_session::Entity")]
+    ReadingSession,
+    #[sea_orm(has_many = "super::reading_status::Entity")]
+    ReadingStatus,
+    #[sea_orm(has_many = "super::share_link::Entity")]
+    ShareLink,
+    #[sea_orm(has_many = "super::tag::Entity")]
+    Tag,
+}
+
+impl Related<super::annotation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Annotation.def()
+    }
 }
 
 impl Related<super::book::Entity> for Entity {
@@ -26,10 +55,64 @@ impl Related<super::book::Entity> for Entity {
     }
 }
 
+impl Related<super::book_change::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BookChange.def()
+    }
+}
+
+impl Related<super::collection::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Collection.def()
+    }
+}
+
+impl Related<super::delivery::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Delivery.def()
+    }
+}
+
+impl Related<super::device::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Device.def()
+    }
+}
+
 impl Related<super::email::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Email.def()
     }
 }
 
+impl Related<super::reading_progress::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingProgress.def()
+    }
+}
+
+impl Related<super::reading_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingSession.def()
+    }
+}
+
+impl Related<super::reading_status::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadingStatus.def()
+    }
+}
+
+impl Related<super::share_link::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShareLink.def()
+    }
+}
+
+impl Related<super::tag::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tag.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
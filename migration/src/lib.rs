@@ -5,6 +5,28 @@ mod m20231124_193135_create_book_table;
 mod m20231124_193703_create_email_table;
 mod m20231124_194004_create_filetype_table;
 mod m20231223_230304_book_info_table;
+mod m20240106_151200_book_info_metadata;
+mod m20240112_201500_filetype_mime;
+mod m20240120_173000_create_book_override_table;
+mod m20240203_110000_book_override_cover;
+mod m20240210_143000_book_size;
+mod m20240217_190000_create_book_change_table;
+mod m20240224_120000_book_timestamps;
+mod m20240302_091500_book_file_name;
+mod m20240309_160000_create_collection_table;
+mod m20240309_160100_create_collection_book_table;
+mod m20240316_103000_create_tag_table;
+mod m20240316_103100_create_book_tag_table;
+mod m20240323_200000_create_reading_progress_table;
+mod m20240330_120000_kosync;
+mod m20240406_150000_create_annotation_table;
+mod m20240413_100000_create_reading_status_table;
+mod m20240413_100100_create_reading_session_table;
+mod m20240420_110000_email_verification;
+mod m20240427_090000_create_device_table;
+mod m20240427_090100_create_delivery_table;
+mod m20240504_100000_create_share_table;
+mod m20240511_120000_create_share_link_table;
 
 pub struct Migrator;
 
@@ -17,6 +39,28 @@ impl MigratorTrait for Migrator {
             Box::new(m20231124_193703_create_email_table::Migration),
             Box::new(m20231124_194004_create_filetype_table::Migration),
             Box::new(m20231223_230304_book_info_table::Migration),
+            Box::new(m20240106_151200_book_info_metadata::Migration),
+            Box::new(m20240112_201500_filetype_mime::Migration),
+            Box::new(m20240120_173000_create_book_override_table::Migration),
+            Box::new(m20240203_110000_book_override_cover::Migration),
+            Box::new(m20240210_143000_book_size::Migration),
+            Box::new(m20240217_190000_create_book_change_table::Migration),
+            Box::new(m20240224_120000_book_timestamps::Migration),
+            Box::new(m20240302_091500_book_file_name::Migration),
+            Box::new(m20240309_160000_create_collection_table::Migration),
+            Box::new(m20240309_160100_create_collection_book_table::Migration),
+            Box::new(m20240316_103000_create_tag_table::Migration),
+            Box::new(m20240316_103100_create_book_tag_table::Migration),
+            Box::new(m20240323_200000_create_reading_progress_table::Migration),
+            Box::new(m20240330_120000_kosync::Migration),
+            Box::new(m20240406_150000_create_annotation_table::Migration),
+            Box::new(m20240413_100000_create_reading_status_table::Migration),
+            Box::new(m20240413_100100_create_reading_session_table::Migration),
+            Box::new(m20240420_110000_email_verification::Migration),
+            Box::new(m20240427_090000_create_device_table::Migration),
+            Box::new(m20240427_090100_create_delivery_table::Migration),
+            Box::new(m20240504_100000_create_share_table::Migration),
+            Box::new(m20240511_120000_create_share_link_table::Migration),
         ]
     }
 }
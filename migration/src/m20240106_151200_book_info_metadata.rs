@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// sqlite can only add one column per ALTER TABLE so every column gets its own statement
+fn columns() -> Vec<ColumnDef> {
+    vec![
+        ColumnDef::new(BookInfo::Language).string().to_owned(),
+        ColumnDef::new(BookInfo::Publisher).string().to_owned(),
+        ColumnDef::new(BookInfo::Description).text().to_owned(),
+        ColumnDef::new(BookInfo::Published).string().to_owned(),
+        ColumnDef::new(BookInfo::Identifiers).json().to_owned(),
+        ColumnDef::new(BookInfo::Subjects).json().to_owned(),
+        ColumnDef::new(BookInfo::Series).string().to_owned(),
+        ColumnDef::new(BookInfo::SeriesIndex).double().to_owned(),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for mut col in columns() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(BookInfo::Table)
+                        .add_column(&mut col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in columns() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(BookInfo::Table)
+                        .drop_column(Alias::new(col.get_column_name()))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum BookInfo {
+    Table,
+    Language,
+    Publisher,
+    Description,
+    Published,
+    Identifiers,
+    Subjects,
+    Series,
+    SeriesIndex,
+}
@@ -0,0 +1,68 @@
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(BookOverride::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BookOverride::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(BookOverride::BookId)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(BookOverride::Title).string())
+                    .col(ColumnDef::new(BookOverride::Creator).string())
+                    .col(ColumnDef::new(BookOverride::Language).string())
+                    .col(ColumnDef::new(BookOverride::Publisher).string())
+                    .col(ColumnDef::new(BookOverride::Description).text())
+                    .col(ColumnDef::new(BookOverride::Published).string())
+                    .col(ColumnDef::new(BookOverride::Series).string())
+                    .col(ColumnDef::new(BookOverride::SeriesIndex).double())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-book_override-book_id")
+                            .from(BookOverride::Table, BookOverride::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BookOverride::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum BookOverride {
+    Table,
+    Id,
+    BookId,
+    Title,
+    Creator,
+    Language,
+    Publisher,
+    Description,
+    Published,
+    Series,
+    SeriesIndex,
+}
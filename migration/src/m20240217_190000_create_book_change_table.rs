@@ -0,0 +1,97 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(BookChange::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BookChange::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(BookChange::UserId).integer().not_null())
+                    // no foreign key, tombstones have to outlive the book
+                    .col(ColumnDef::new(BookChange::BookId).integer().not_null())
+                    .col(ColumnDef::new(BookChange::Kind).string().not_null())
+                    .col(
+                        ColumnDef::new(BookChange::ChangedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-book_change-user_id")
+                            .from(BookChange::Table, BookChange::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-book_change-user_id-id")
+                    .table(BookChange::Table)
+                    .col(BookChange::UserId)
+                    .col(BookChange::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        // books that are already there show up as created in the first sync
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let existing = Query::select()
+            .column(Book::UserId)
+            .column(Book::Id)
+            .expr(Expr::val("created"))
+            .expr(Expr::val(now))
+            .from(Book::Table)
+            .order_by(Book::Id, Order::Asc)
+            .to_owned();
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(BookChange::Table)
+                    .columns([
+                        BookChange::UserId,
+                        BookChange::BookId,
+                        BookChange::Kind,
+                        BookChange::ChangedAt,
+                    ])
+                    .select_from(existing)
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BookChange::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum BookChange {
+    Table,
+    Id,
+    UserId,
+    BookId,
+    Kind,
+    ChangedAt,
+}
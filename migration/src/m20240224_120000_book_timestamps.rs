@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite can only add one column per statement
+        for col in [Book::CreatedAt, Book::UpdatedAt] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Book::Table)
+                        .add_column(ColumnDef::new(col).big_integer().not_null().default(0))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // existing books get dated by their stored file once the server is up, see `backfill_timestamps`
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in [Book::CreatedAt, Book::UpdatedAt] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Book::Table)
+                        .drop_column(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Book {
+    Table,
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,74 @@
+use super::m20231124_193135_create_book_table::Book;
+use super::m20240309_160000_create_collection_table::Collection;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CollectionBook::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CollectionBook::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CollectionBook::CollectionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CollectionBook::BookId).integer().not_null())
+                    .col(
+                        ColumnDef::new(CollectionBook::Position)
+                            .integer()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx-collection_book-collection_id-book_id")
+                            .col(CollectionBook::CollectionId)
+                            .col(CollectionBook::BookId)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-collection_book-collection_id")
+                            .from(CollectionBook::Table, CollectionBook::CollectionId)
+                            .to(Collection::Table, Collection::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-collection_book-book_id")
+                            .from(CollectionBook::Table, CollectionBook::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CollectionBook::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum CollectionBook {
+    Table,
+    Id,
+    CollectionId,
+    BookId,
+    Position,
+}
@@ -0,0 +1,59 @@
+use super::m20220101_000001_create_user_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tag::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tag::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Tag::UserId).integer().not_null())
+                    .col(ColumnDef::new(Tag::Name).string().not_null())
+                    // tags are the same if they only differ in case, the lowercased name keeps them unique
+                    .col(ColumnDef::new(Tag::NameKey).string().not_null())
+                    .index(
+                        Index::create()
+                            .name("idx-tag-user_id-name_key")
+                            .col(Tag::UserId)
+                            .col(Tag::NameKey)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-tag-user_id")
+                            .from(Tag::Table, Tag::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Tag::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Tag {
+    Table,
+    Id,
+    UserId,
+    Name,
+    NameKey,
+}
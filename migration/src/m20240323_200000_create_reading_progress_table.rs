@@ -0,0 +1,80 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReadingProgress::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ReadingProgress::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ReadingProgress::UserId).integer().not_null())
+                    .col(ColumnDef::new(ReadingProgress::BookId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ReadingProgress::Percentage)
+                            .double()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ReadingProgress::Position).text())
+                    .col(ColumnDef::new(ReadingProgress::Device).string())
+                    .col(
+                        ColumnDef::new(ReadingProgress::UpdatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx-reading_progress-user_id-book_id")
+                            .col(ReadingProgress::UserId)
+                            .col(ReadingProgress::BookId)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_progress-user_id")
+                            .from(ReadingProgress::Table, ReadingProgress::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_progress-book_id")
+                            .from(ReadingProgress::Table, ReadingProgress::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReadingProgress::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ReadingProgress {
+    Table,
+    Id,
+    UserId,
+    BookId,
+    Percentage,
+    Position,
+    Device,
+    UpdatedAt,
+}
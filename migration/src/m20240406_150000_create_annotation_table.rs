@@ -0,0 +1,93 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Annotation::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Annotation::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Annotation::UserId).integer().not_null())
+                    .col(ColumnDef::new(Annotation::BookId).integer().not_null())
+                    .col(ColumnDef::new(Annotation::Position).text().not_null())
+                    .col(ColumnDef::new(Annotation::EndPosition).text())
+                    .col(ColumnDef::new(Annotation::Chapter).string())
+                    .col(ColumnDef::new(Annotation::Page).integer())
+                    .col(ColumnDef::new(Annotation::Text).text())
+                    .col(ColumnDef::new(Annotation::Note).text())
+                    .col(ColumnDef::new(Annotation::Color).string())
+                    .col(
+                        ColumnDef::new(Annotation::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Annotation::UpdatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-annotation-user_id")
+                            .from(Annotation::Table, Annotation::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-annotation-book_id")
+                            .from(Annotation::Table, Annotation::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-annotation-user_id-book_id")
+                    .table(Annotation::Table)
+                    .col(Annotation::UserId)
+                    .col(Annotation::BookId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Annotation::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Annotation {
+    Table,
+    Id,
+    UserId,
+    BookId,
+    Position,
+    EndPosition,
+    Chapter,
+    Page,
+    Text,
+    Note,
+    Color,
+    CreatedAt,
+    UpdatedAt,
+}
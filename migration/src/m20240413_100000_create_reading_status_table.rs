@@ -0,0 +1,76 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReadingStatus::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ReadingStatus::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ReadingStatus::UserId).integer().not_null())
+                    .col(ColumnDef::new(ReadingStatus::BookId).integer().not_null())
+                    .col(ColumnDef::new(ReadingStatus::Status).string().not_null())
+                    .col(ColumnDef::new(ReadingStatus::StartedAt).big_integer())
+                    .col(ColumnDef::new(ReadingStatus::FinishedAt).big_integer())
+                    .col(
+                        ColumnDef::new(ReadingStatus::UpdatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx-reading_status-user_id-book_id")
+                            .col(ReadingStatus::UserId)
+                            .col(ReadingStatus::BookId)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_status-user_id")
+                            .from(ReadingStatus::Table, ReadingStatus::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_status-book_id")
+                            .from(ReadingStatus::Table, ReadingStatus::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReadingStatus::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ReadingStatus {
+    Table,
+    Id,
+    UserId,
+    BookId,
+    Status,
+    StartedAt,
+    FinishedAt,
+    UpdatedAt,
+}
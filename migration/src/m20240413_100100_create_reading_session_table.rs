@@ -0,0 +1,82 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReadingSession::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ReadingSession::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ReadingSession::UserId).integer().not_null())
+                    .col(ColumnDef::new(ReadingSession::BookId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ReadingSession::StartedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ReadingSession::EndedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ReadingSession::Pages).integer())
+                    .col(ColumnDef::new(ReadingSession::Device).string())
+                    // devices resend sessions they aren't sure got through
+                    .index(
+                        Index::create()
+                            .name("idx-reading_session-user_id-book_id-started_at")
+                            .col(ReadingSession::UserId)
+                            .col(ReadingSession::BookId)
+                            .col(ReadingSession::StartedAt)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_session-user_id")
+                            .from(ReadingSession::Table, ReadingSession::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reading_session-book_id")
+                            .from(ReadingSession::Table, ReadingSession::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReadingSession::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ReadingSession {
+    Table,
+    Id,
+    UserId,
+    BookId,
+    StartedAt,
+    EndedAt,
+    Pages,
+    Device,
+}
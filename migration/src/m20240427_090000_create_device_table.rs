@@ -0,0 +1,60 @@
+use super::m20220101_000001_create_user_table::User;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Device::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Device::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Device::UserId).integer().not_null())
+                    .col(ColumnDef::new(Device::Name).string().not_null())
+                    .col(ColumnDef::new(Device::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(Device::LastSeenAt).big_integer())
+                    .index(
+                        Index::create()
+                            .name("idx-device-user_id-name")
+                            .col(Device::UserId)
+                            .col(Device::Name)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-device-user_id")
+                            .from(Device::Table, Device::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Device::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Device {
+    Table,
+    Id,
+    UserId,
+    Name,
+    CreatedAt,
+    LastSeenAt,
+}
@@ -0,0 +1,85 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use super::m20240427_090000_create_device_table::Device;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Delivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Delivery::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Delivery::UserId).integer().not_null())
+                    .col(ColumnDef::new(Delivery::DeviceId).integer().not_null())
+                    .col(ColumnDef::new(Delivery::BookId).integer().not_null())
+                    .col(ColumnDef::new(Delivery::Status).string().not_null())
+                    .col(ColumnDef::new(Delivery::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(Delivery::FetchedAt).big_integer())
+                    .col(ColumnDef::new(Delivery::DeliveredAt).big_integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-delivery-user_id")
+                            .from(Delivery::Table, Delivery::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-delivery-device_id")
+                            .from(Delivery::Table, Delivery::DeviceId)
+                            .to(Device::Table, Device::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-delivery-book_id")
+                            .from(Delivery::Table, Delivery::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-delivery-device_id-status")
+                    .table(Delivery::Table)
+                    .col(Delivery::DeviceId)
+                    .col(Delivery::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Delivery::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Delivery {
+    Table,
+    Id,
+    UserId,
+    DeviceId,
+    BookId,
+    Status,
+    CreatedAt,
+    FetchedAt,
+    DeliveredAt,
+}
@@ -0,0 +1,89 @@
+use super::m20220101_000001_create_user_table::User;
+use super::m20231124_193135_create_book_table::Book;
+use super::m20240309_160000_create_collection_table::Collection;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Share::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Share::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Share::UserId).integer().not_null())
+                    .col(ColumnDef::new(Share::RecipientId).integer().not_null())
+                    .col(ColumnDef::new(Share::BookId).integer())
+                    .col(ColumnDef::new(Share::CollectionId).integer())
+                    .col(ColumnDef::new(Share::Mode).string().not_null())
+                    .col(ColumnDef::new(Share::CreatedAt).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-share-user_id")
+                            .from(Share::Table, Share::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-share-recipient_id")
+                            .from(Share::Table, Share::RecipientId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-share-book_id")
+                            .from(Share::Table, Share::BookId)
+                            .to(Book::Table, Book::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-share-collection_id")
+                            .from(Share::Table, Share::CollectionId)
+                            .to(Collection::Table, Collection::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-share-recipient_id")
+                    .table(Share::Table)
+                    .col(Share::RecipientId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Share::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Share {
+    Table,
+    Id,
+    UserId,
+    RecipientId,
+    BookId,
+    CollectionId,
+    Mode,
+    CreatedAt,
+}
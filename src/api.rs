@@ -1,16 +1,68 @@
-use actix_web::{middleware::Compat, web};
+use actix_web::{error, middleware::Compat, web, HttpResponse};
 use actix_web_httpauth::middleware::HttpAuthentication;
+use sea_orm::DbErr;
+use serde::Serialize;
 
+use crate::{ErrorResponse, Response};
+
+pub mod annotation;
 pub mod book;
+pub mod changes;
+pub mod collection;
+pub mod device;
+pub mod email;
+pub mod kosync;
+pub mod link;
+pub mod progress;
+pub mod reading;
+pub mod share;
+pub mod tag;
 pub mod user;
 
+fn error_response(e: impl ToString) -> ErrorResponse {
+    ErrorResponse {
+        status: "error".to_string(),
+        error: e.to_string(),
+    }
+}
+
+pub(crate) fn server_error(e: impl ToString) -> actix_web::Error {
+    error::ErrorInternalServerError(error_response(e))
+}
+
+pub(crate) fn db_error(e: DbErr) -> actix_web::Error {
+    server_error(e)
+}
+
+pub(crate) fn not_found(e: impl ToString) -> actix_web::Error {
+    error::ErrorNotFound(error_response(e))
+}
+
+pub(crate) fn bad_request(e: impl ToString) -> actix_web::Error {
+    error::ErrorBadRequest(error_response(e))
+}
+
+pub(crate) fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(Response::new(data))
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     let auth = HttpAuthentication::bearer(crate::auth::validator);
 
     cfg.service(
         web::scope("/api")
             .wrap(Compat::new(auth))
+            .configure(annotation::configure)
             .configure(book::configure)
+            .configure(changes::configure)
+            .configure(collection::configure)
+            .configure(device::configure)
+            .configure(email::configure)
+            .configure(link::configure)
+            .configure(progress::configure)
+            .configure(reading::configure)
+            .configure(share::configure)
+            .configure(tag::configure)
             .configure(user::configure),
     );
 }
@@ -18,9 +70,12 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
 pub fn configure_no_auth(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/na")
+            .configure(email::configure_na)
+            .configure(link::configure_na)
             .configure(user::configure_na)
             .service(health),
-    );
+    )
+    .service(web::scope("/kosync").configure(kosync::configure));
 }
 
 #[actix_web::get("/health")]
@@ -0,0 +1,412 @@
+use super::book::{full_books, BookId, FullBook};
+use super::{bad_request, db_error, not_found, ok};
+use crate::AuthData;
+use actix_web::http::header::ContentDisposition;
+use actix_web::{delete, get, put, web, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use entity::annotation::ActiveModel as AActiveModel;
+use entity::annotation::Column as ACol;
+use entity::annotation::Model as AModel;
+use entity::book::Column as BookCol;
+use entity::prelude::{Annotation, Book, FileType};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, TransactionTrait, TryIntoModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+#[derive(Deserialize)]
+struct AnnotationPath {
+    book_id: i32,
+    annotation_id: i32,
+}
+
+/// An annotation as a device sends it, matched to the ones we have by where it starts and ends.
+#[derive(Deserialize)]
+struct AnnotationRequest {
+    position: String,
+    end_position: Option<String>,
+    chapter: Option<String>,
+    page: Option<i32>,
+    text: Option<String>,
+    note: Option<String>,
+    color: Option<String>,
+    /// unix timestamps, now if left out
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    #[serde(alias = "md")]
+    Markdown,
+    Json,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Serialize)]
+struct BookAnnotations {
+    book_id: i32,
+    title: String,
+    creator: Option<String>,
+    annotations: Vec<AModel>,
+}
+
+impl BookAnnotations {
+    fn new(book: &FullBook, annotations: Vec<AModel>) -> Self {
+        BookAnnotations {
+            book_id: book.id,
+            title: book
+                .meta
+                .as_ref()
+                .map_or_else(|| book.title.clone(), |m| m.title.clone()),
+            creator: book.meta.as_ref().map(|m| m.creator.clone()),
+            annotations,
+        }
+    }
+
+    fn markdown(&self, out: &mut String) {
+        let _ = writeln!(out, "# {}\n", self.title);
+        if let Some(creator) = self.creator.as_ref().filter(|c| !c.is_empty()) {
+            let _ = writeln!(out, "*{}*\n", creator);
+        }
+        let mut chapter = None;
+        for a in &self.annotations {
+            if a.chapter.is_some() && a.chapter != chapter {
+                chapter = a.chapter.clone();
+                let _ = writeln!(out, "## {}\n", a.chapter.as_deref().unwrap_or_default());
+            }
+            match &a.text {
+                Some(text) => {
+                    for line in text.lines() {
+                        let _ = writeln!(out, "> {}", line);
+                    }
+                    out.push('\n');
+                }
+                None if a.note.is_none() => out.push_str("Bookmark\n\n"),
+                None => {}
+            }
+            if let Some(note) = &a.note {
+                let _ = writeln!(out, "{}\n", note);
+            }
+            let mut details = vec![];
+            if let Some(page) = a.page {
+                details.push(format!("page {}", page));
+            }
+            if let Some(date) = NaiveDateTime::from_timestamp_opt(a.created_at, 0) {
+                details.push(date.format("%Y-%m-%d %H:%M").to_string());
+            }
+            let _ = writeln!(out, "*{}*\n", details.join(" · "));
+        }
+    }
+}
+
+/// Reading order as far as we can tell without understanding xpointers, ones without a page last.
+fn sort(annotations: &mut [AModel]) {
+    annotations.sort_by_key(|a| (a.page.is_none(), a.page, a.created_at, a.id));
+}
+
+async fn book_annotations(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+) -> Result<Vec<AModel>, DbErr> {
+    let mut annotations = Annotation::find()
+        .filter(ACol::UserId.eq(user_id))
+        .filter(ACol::BookId.eq(book_id))
+        .all(db)
+        .await?;
+    sort(&mut annotations);
+    Ok(annotations)
+}
+
+fn export(books: &[BookAnnotations], format: ExportFormat, name: &str) -> HttpResponse {
+    let (body, content_type, ext) = match format {
+        ExportFormat::Markdown => {
+            let mut out = String::new();
+            for book in books {
+                book.markdown(&mut out);
+            }
+            (out, "text/markdown; charset=utf-8", "md")
+        }
+        ExportFormat::Json => (
+            serde_json::to_string_pretty(books).unwrap_or_default(),
+            "application/json",
+            "json",
+        ),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition::attachment(format!("{}.{}", name, ext)))
+        .body(body)
+}
+
+#[get("/book/{book_id}/annotations")]
+async fn list(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let annotations = book_annotations(db, user.id, book.id)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(annotations))
+}
+
+/// Takes everything a device has for a book at once, keeping whichever side edited an
+/// annotation last.
+#[put("/book/{book_id}/annotations")]
+async fn upsert(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<Vec<AnnotationRequest>>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    if req_data.iter().any(|a| a.position.is_empty()) {
+        return Err(bad_request("Every annotation needs a position."));
+    }
+
+    let txn = db.begin().await.map_err(db_error)?;
+    let mut known: HashMap<(String, Option<String>), AModel> = Annotation::find()
+        .filter(ACol::UserId.eq(user.id))
+        .filter(ACol::BookId.eq(book.id))
+        .all(&txn)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|a| ((a.position.clone(), a.end_position.clone()), a))
+        .collect();
+    let now = Utc::now().timestamp();
+    for req in req_data.into_inner() {
+        let updated_at = req.updated_at.or(req.created_at).unwrap_or(now);
+        let key = (req.position.clone(), req.end_position.clone());
+        let mut annotation: AActiveModel = match known.remove(&key) {
+            Some(current) if current.updated_at > updated_at => {
+                known.insert(key, current);
+                continue;
+            }
+            Some(current) => current.into(),
+            None => AActiveModel {
+                id: ActiveValue::NotSet,
+                user_id: ActiveValue::Set(user.id),
+                book_id: ActiveValue::Set(book.id),
+                position: ActiveValue::Set(req.position),
+                end_position: ActiveValue::Set(req.end_position),
+                created_at: ActiveValue::Set(req.created_at.unwrap_or(updated_at)),
+                ..Default::default()
+            },
+        };
+        annotation.chapter = ActiveValue::Set(req.chapter);
+        annotation.page = ActiveValue::Set(req.page);
+        annotation.text = ActiveValue::Set(req.text);
+        annotation.note = ActiveValue::Set(req.note);
+        annotation.color = ActiveValue::Set(req.color);
+        annotation.updated_at = ActiveValue::Set(updated_at);
+        let annotation = annotation.save(&txn).await.map_err(db_error)?;
+        // the same highlight twice in one upload lands on the same row
+        known.insert(key, annotation.try_into_model().map_err(db_error)?);
+    }
+    txn.commit().await.map_err(db_error)?;
+
+    let mut annotations: Vec<AModel> = known.into_values().collect();
+    sort(&mut annotations);
+    Ok(ok(annotations))
+}
+
+#[delete("/book/{book_id}/annotations/{annotation_id}")]
+async fn remove(
+    path: web::Path<AnnotationPath>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = Annotation::delete_many()
+        .filter(ACol::Id.eq(path.annotation_id))
+        .filter(ACol::BookId.eq(path.book_id))
+        .filter(ACol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+#[get("/book/{book_id}/annotations/export")]
+async fn export_book(
+    bookid: web::Path<BookId>,
+    query: web::Query<ExportQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let annotations = book_annotations(db, user.id, book.id)
+        .await
+        .map_err(db_error)?;
+    let book = BookAnnotations::new(&book, annotations);
+    let name = format!("{} - annotations", book.title);
+    Ok(export(&[book], query.format, &name))
+}
+
+/// Everything the user ever highlighted, one section per book.
+#[get("/annotations/export")]
+async fn export_all(
+    query: web::Query<ExportQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let mut by_book: BTreeMap<i32, Vec<AModel>> = BTreeMap::new();
+    for a in Annotation::find()
+        .filter(ACol::UserId.eq(user.id))
+        .all(db)
+        .await
+        .map_err(db_error)?
+    {
+        by_book.entry(a.book_id).or_default().push(a);
+    }
+    let books = Book::find()
+        .filter(BookCol::UserId.eq(user.id))
+        .filter(BookCol::Id.is_in(by_book.keys().copied()))
+        .find_also_related(FileType)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut books: Vec<BookAnnotations> = full_books(db, books)
+        .await
+        .map_err(db_error)?
+        .iter()
+        .map(|book| {
+            let mut annotations = by_book.remove(&book.id).unwrap_or_default();
+            sort(&mut annotations);
+            BookAnnotations::new(book, annotations)
+        })
+        .collect();
+    books.sort_by_cached_key(|b| b.title.to_lowercase());
+    Ok(export(&books, query.format, "annotations"))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(upsert)
+        .service(export_book)
+        .service(remove)
+        .service(export_all);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+    use serde_json::{json, Value};
+
+    fn annotation(id: i32, page: Option<i32>, chapter: &str, text: Option<&str>) -> AModel {
+        AModel {
+            id,
+            user_id: 1,
+            book_id: 1,
+            position: format!("/body/p[{id}]"),
+            end_position: None,
+            chapter: Some(chapter.to_string()),
+            page,
+            text: text.map(str::to_string),
+            note: None,
+            color: None,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn markdown_groups_by_chapter() {
+        let mut annotations = vec![
+            annotation(3, None, "Two", None),
+            annotation(2, Some(9), "Two", Some("second")),
+            annotation(1, Some(4), "One", Some("first line\nsecond line")),
+        ];
+        annotations[0].note = Some("remember this".to_string());
+        sort(&mut annotations);
+        let book = BookAnnotations {
+            book_id: 1,
+            title: "A Book".to_string(),
+            creator: Some("Someone".to_string()),
+            annotations,
+        };
+        let mut out = String::new();
+        book.markdown(&mut out);
+        assert_eq!(
+            out,
+            "# A Book\n\n*Someone*\n\n\
+             ## One\n\n> first line\n> second line\n\n*page 4 · 2023-11-14 22:13*\n\n\
+             ## Two\n\n> second\n\n*page 9 · 2023-11-14 22:13*\n\n\
+             remember this\n\n*2023-11-14 22:13*\n\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn upsert_keeps_the_later_edit() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let pdf = b"%PDF-1.4\n%%EOF\n".to_vec();
+        let book = add_book(&config, &db, &search, user.id, Some("a.pdf".into()), pdf)
+            .await
+            .unwrap();
+        let put = |body: Value| {
+            TestRequest::put()
+                .uri(&format!("/api/book/{book}/annotations"))
+                .set_json(body)
+                .to_request()
+        };
+
+        let body = json!([
+            { "position": "a", "end_position": "b", "text": "old", "updated_at": 100 },
+            { "position": "a", "end_position": "b", "text": "newer", "updated_at": 200 },
+            { "position": "c", "note": "bookmark", "updated_at": 100 },
+        ]);
+        let res: Value = call_and_read_body_json(&app, put(body)).await;
+        let texts: Vec<&Value> = res["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| &a["text"])
+            .collect();
+        assert_eq!(texts, [&json!("newer"), &Value::Null]);
+
+        // another device that hasn't seen the edit yet doesn't undo it
+        let body =
+            json!([{ "position": "a", "end_position": "b", "text": "stale", "updated_at": 150 }]);
+        call_service(&app, put(body)).await;
+        let stored = book_annotations(&db, user.id, book).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            stored
+                .iter()
+                .find(|a| a.position == "a")
+                .unwrap()
+                .text
+                .as_deref(),
+            Some("newer")
+        );
+
+        let body = json!([{ "position": "" }]);
+        let res = call_service(&app, put(body)).await;
+        assert_eq!(res.status(), 400);
+    }
+}
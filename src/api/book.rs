@@ -1,80 +1,268 @@
+use super::{bad_request, changes, db_error, kosync, not_found, ok, server_error, tag};
 use crate::config::Config;
+use crate::metadata::{self, detect};
+use crate::search::Search;
+use crate::{covers, storage};
 // use hex_literal::hex;
 use crate::{AuthData, ErrorResponse, Response};
 use actix_multipart::form::{tempfile::TempFile, MultipartForm};
-use actix_web::http::header::ContentDisposition;
-use actix_web::{delete, get, put};
-use actix_web::{error, web, HttpResponse};
+use actix_web::http::header::{ContentDisposition, ContentEncoding};
+use actix_web::{delete, get, patch, put};
+use actix_web::{error, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use log::{debug, warn};
 
 use entity::book::ActiveModel as BookActiveModel;
 use entity::book::Column as BookCol;
+use entity::book::Model as BookModel;
+use entity::book::Relation as BookRelation;
 
-use entity::book_info::ActiveModel as BookInfoActiveModel;
 use entity::book_info::Column as BICol;
 use entity::book_info::Model as BIModel;
 
+use entity::book_override::ActiveModel as BOActiveModel;
+use entity::book_override::Column as BOCol;
+use entity::book_override::Model as BOModel;
+
+use entity::book_tag::Column as BTCol;
+use entity::collection_book::Column as CBCol;
+use entity::prelude::{BookTag, Tag};
+use entity::tag::Column as TagCol;
+
 use entity::file_type::ActiveModel as FTActiveModel;
 use entity::file_type::Column as FTCol;
 use entity::file_type::Model as FTModel;
 use entity::prelude::Book;
 use entity::prelude::BookInfo;
+use entity::prelude::BookOverride;
 use entity::prelude::FileType;
-use epub::doc::EpubDoc;
 // use entity::user::{self, ActiveModel, Entity};
+use crate::api::user::LimitQuery;
 use actix_files::NamedFile;
 use hex::encode;
-use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
-use serde::{Deserialize, Serialize};
+use sea_orm::sea_query::{Expr, Func, LikeExpr, Query, SimpleExpr};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType,
+    Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::{Deserialize, Deserializer, Serialize};
 use sha2::{Digest, Sha256};
-use std::io::{Cursor, Read, Write};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
 use std::path::Path;
 #[derive(Deserialize)]
-struct BookId {
-    book_id: i32,
+pub(crate) struct BookId {
+    pub(crate) book_id: i32,
 }
 
 #[derive(Serialize)]
-struct FullBook {
+pub(crate) struct FullBook {
     pub id: i32,
     pub title: String,
     pub hash: String,
     pub user_id: i32,
+    /// unix timestamps of the upload and the last edit
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// bytes, `None` if the file went missing before we could measure it
+    pub size: Option<i64>,
+    /// name of the file as it was uploaded
+    pub file_name: Option<String>,
     pub file_type: FTModel,
     pub meta: Option<BIModel>,
+    /// the user replaced the cover from the file with their own
+    pub custom_cover: bool,
+    pub tags: Vec<String>,
+}
+
+impl FullBook {
+    /// What the file is called when it leaves the server, the current title and the type it really is.
+    pub(crate) fn download_name(&self) -> String {
+        format!("{}.{}", self.title, self.file_type.name)
+    }
 }
 
 impl BookId {
     pub async fn get(&self, uid: i32, pool: &DatabaseConnection) -> Result<FullBook, String> {
         match Book::find_by_id(self.book_id)
             .filter(BookCol::UserId.eq(uid))
+            .find_also_related(FileType)
             .one(pool)
             .await
         {
-            Ok(Some(book)) => {
-                let ft = FileType::find_by_id(book.file_tyoe)
-                    .one(pool)
-                    .await
-                    .unwrap()
-                    .unwrap();
-                let bi = BookInfo::find()
-                    .filter(BICol::BookHash.eq(&book.hash))
-                    .one(pool)
-                    .await
-                    .unwrap();
-                Ok(FullBook {
-                    file_type: ft,
-                    id: book.id,
-                    title: book.title,
-                    hash: book.hash,
-                    user_id: book.user_id,
-                    meta: bi,
-                })
-            }
+            Ok(Some(book)) => match full_books(pool, vec![book]).await {
+                Ok(mut books) => Ok(books.remove(0)),
+                Err(e) => Err(e.to_string()),
+            },
             Ok(None) => Err("No such book found.".to_string()),
             Err(e) => Err(e.to_string()),
         }
     }
 }
+
+/// Puts together `FullBook`s with one query per table, however many books there are.
+pub(crate) async fn full_books(
+    db: &DatabaseConnection,
+    books: Vec<(BookModel, Option<FTModel>)>,
+) -> Result<Vec<FullBook>, DbErr> {
+    let hashes: HashSet<&str> = books.iter().map(|(b, _)| b.hash.as_str()).collect();
+    let infos: HashMap<String, BIModel> = BookInfo::find()
+        .filter(BICol::BookHash.is_in(hashes))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bi| (bi.book_hash.clone(), bi))
+        .collect();
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for (link, tag) in BookTag::find()
+        .filter(BTCol::BookId.is_in(books.iter().map(|(b, _)| b.id)))
+        .find_also_related(Tag)
+        .order_by_asc(TagCol::Name)
+        .all(db)
+        .await?
+    {
+        if let Some(tag) = tag {
+            tags.entry(link.book_id).or_default().push(tag.name);
+        }
+    }
+    let mut edits: HashMap<i32, BOModel> = BookOverride::find()
+        .filter(BOCol::BookId.is_in(books.iter().map(|(b, _)| b.id)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bo| (bo.book_id, bo))
+        .collect();
+    Ok(books
+        .into_iter()
+        .map(|(book, ft)| {
+            let edits = edits.remove(&book.id);
+            // several of the books can be the same file, so no taking it out of the map
+            let meta = infos.get(&book.hash).cloned();
+            FullBook {
+                // only books whose file type row went missing, don't take the list down for them
+                file_type: ft.unwrap_or_else(|| FTModel {
+                    id: book.file_tyoe,
+                    name: "unk".to_string(),
+                    mime: None,
+                }),
+                id: book.id,
+                // a renamed book goes by its new name everywhere, downloads included
+                title: edits
+                    .as_ref()
+                    .and_then(|e| e.title.clone())
+                    .unwrap_or(book.title),
+                hash: book.hash,
+                user_id: book.user_id,
+                created_at: book.created_at,
+                updated_at: book.updated_at,
+                size: book.size,
+                file_name: book.file_name,
+                custom_cover: edits.as_ref().is_some_and(|e| e.cover_mime.is_some()),
+                tags: tags.remove(&book.id).unwrap_or_default(),
+                meta: meta.map(|bi| merge(bi, edits)),
+            }
+        })
+        .collect())
+}
+
+/// Layers a user's edits on top of the metadata we extracted from the file.
+pub(crate) fn merge(meta: BIModel, edits: Option<BOModel>) -> BIModel {
+    let Some(edits) = edits else {
+        return meta;
+    };
+    BIModel {
+        title: edits.title.unwrap_or(meta.title),
+        creator: edits.creator.unwrap_or(meta.creator),
+        language: edits.language.or(meta.language),
+        publisher: edits.publisher.or(meta.publisher),
+        description: edits.description.or(meta.description),
+        published: edits.published.or(meta.published),
+        series: edits.series.or(meta.series),
+        series_index: edits.series_index.or(meta.series_index),
+        cover_mime: edits.cover_mime.or(meta.cover_mime),
+        ..meta
+    }
+}
+
+/// Lets us tell a missing field (leave it alone) from a `null` one (drop the edit).
+fn present<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    de: D,
+) -> Result<Option<Option<T>>, D::Error> {
+    Option::<T>::deserialize(de).map(Some)
+}
+
+#[derive(Deserialize, Debug)]
+struct EditBook {
+    #[serde(default, deserialize_with = "present")]
+    title: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    creator: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    language: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    publisher: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    published: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    series: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    series_index: Option<Option<f64>>,
+}
+
+/// Loads the user's edits of a book, or a fresh set if they haven't changed anything yet.
+async fn book_edits(
+    db: &DatabaseConnection,
+    config: &Config,
+    book: &FullBook,
+) -> Result<BOActiveModel, DbErr> {
+    // edits go on top of book_info, books from before we extracted anything don't have one yet
+    if book.meta.is_none() {
+        let info = metadata::Metadata::default()
+            .into_book_info(config, &book.hash, &book.title)
+            .map_err(|e| DbErr::Custom(e.to_string()))?;
+        BookInfo::insert(info).exec(db).await?;
+    }
+    Ok(
+        match BookOverride::find()
+            .filter(BOCol::BookId.eq(book.id))
+            .one(db)
+            .await?
+        {
+            Some(edits) => edits.into(),
+            None => BOActiveModel {
+                book_id: ActiveValue::Set(book.id),
+                ..Default::default()
+            },
+        },
+    )
+}
+
+#[derive(Debug, MultipartForm)]
+struct CoverForm {
+    #[multipart(rename = "file")]
+    image: TempFile,
+}
+
+/// Blob name (without `.bin`) of a cover a user uploaded for their copy of a book.
+fn custom_cover_name(hash: &str, book_id: i32) -> String {
+    format!("{}-cover-{}", hash, book_id)
+}
+
+/// Puts the current state of a book into the search index. The index can always be rebuilt,
+/// so failing to update it only gets logged.
+async fn reindex(search: &web::Data<Search>, book: &FullBook, content: Option<String>) {
+    let (search, id, user_id) = (search.clone(), book.id, book.user_id);
+    let (title, meta) = (book.title.clone(), book.meta.clone());
+    let res = web::block(move || search.index(id, user_id, &title, meta.as_ref(), content)).await;
+    match res {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to index book {}: {}", id, e),
+        Err(e) => warn!("Failed to index book {}: {}", id, e),
+    }
+}
+
 #[derive(Debug, MultipartForm)]
 struct UploadForm {
     #[multipart(rename = "file")]
@@ -88,200 +276,723 @@ async fn book_info(
     AuthData(user): AuthData,
 ) -> actix_web::Result<impl actix_web::Responder> {
     match bookid.get(user.id, &db).await {
-        Ok(b) => Ok(HttpResponse::Ok().json(Response {
-            status: "ok".to_string(),
-            data: b,
-        })),
-        Err(e) => Err(error::ErrorNotFound(ErrorResponse {
-            status: "error".to_string(),
-            error: e,
-        })),
+        Ok(b) => Ok(ok(b)),
+        Err(e) => Err(not_found(e)),
+    }
+}
+
+/// Serves a stored blob, decrypting it in memory if it was stored encrypted.
+/// Blobs are books and pictures that are compressed already, so they skip the compression
+/// middleware and keep their `Content-Length`.
+async fn serve_blob(
+    req: &HttpRequest,
+    config: &Config,
+    name: &str,
+    mime: Option<&str>,
+    disposition: ContentDisposition,
+) -> actix_web::Result<HttpResponse> {
+    if storage::needs_decryption(config, name)? {
+        let data = storage::read(config, name)?;
+        Ok(HttpResponse::Ok()
+            .content_type(mime.unwrap_or("application/octet-stream"))
+            .insert_header(disposition)
+            .insert_header(ContentEncoding::Identity)
+            .body(data))
+    } else {
+        let mut file = NamedFile::open_async(storage::path(config, name))
+            .await?
+            .set_content_disposition(disposition)
+            .set_content_encoding(ContentEncoding::Identity);
+        if let Some(mime) = mime {
+            file = file.set_content_type(mime.parse().map_err(error::ErrorInternalServerError)?);
+        }
+        Ok(file.into_response(req))
+    }
+}
+
+#[patch("/book/{book_id}")]
+async fn edit(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    search: web::Data<Search>,
+    AuthData(user): AuthData,
+    req_data: web::Json<EditBook>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+
+    let mut edits = book_edits(db, &config, &book).await.map_err(db_error)?;
+    let req_data = req_data.into_inner();
+    if let Some(title) = req_data.title {
+        edits.title = ActiveValue::Set(title);
+    }
+    if let Some(creator) = req_data.creator {
+        edits.creator = ActiveValue::Set(creator);
+    }
+    if let Some(language) = req_data.language {
+        edits.language = ActiveValue::Set(language);
+    }
+    if let Some(publisher) = req_data.publisher {
+        edits.publisher = ActiveValue::Set(publisher);
+    }
+    if let Some(description) = req_data.description {
+        edits.description = ActiveValue::Set(description);
+    }
+    if let Some(published) = req_data.published {
+        edits.published = ActiveValue::Set(published);
+    }
+    if let Some(series) = req_data.series {
+        edits.series = ActiveValue::Set(series);
+    }
+    if let Some(series_index) = req_data.series_index {
+        edits.series_index = ActiveValue::Set(series_index);
+    }
+    edits.save(db).await.map_err(db_error)?;
+    changes::record(db, user.id, book.id, changes::UPDATED)
+        .await
+        .map_err(db_error)?;
+
+    match bookid.get(user.id, db).await {
+        Ok(b) => {
+            reindex(&search, &b, None).await;
+            Ok(ok(b))
+        }
+        Err(e) => Err(not_found(e)),
     }
 }
 
 #[get("/book/{book_id}/dl")]
 async fn download(
+    req: HttpRequest,
     bookid: web::Path<BookId>,
     config: web::Data<Config>,
     db: web::Data<DatabaseConnection>,
     AuthData(user): AuthData,
-) -> NamedFile {
-    let book = bookid.get(user.id, &db).await.unwrap(); // we dont care just fail lol
-    let fp = format!("{}/{}.bin", config.filepath, book.hash);
-    NamedFile::open_async(fp)
-        .await
-        .unwrap()
-        .set_content_disposition(ContentDisposition::attachment(format!(
-            "{}.{}",
-            book.title, book.file_type.name
-        )))
+) -> actix_web::Result<HttpResponse> {
+    let book = bookid.get(user.id, &db).await.map_err(not_found)?;
+    serve_book(&req, &config, book).await
+}
+
+/// Sends the book's file named after its title.
+pub(crate) async fn serve_book(
+    req: &HttpRequest,
+    config: &Config,
+    book: FullBook,
+) -> actix_web::Result<HttpResponse> {
+    serve_blob(
+        req,
+        config,
+        &format!("{}.bin", book.hash),
+        book.file_type.mime.as_deref(),
+        ContentDisposition::attachment(book.download_name()),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct CoverQuery {
+    #[serde(default)]
+    size: covers::Size,
+    #[serde(default)]
+    format: covers::Format,
 }
 
 #[get("/book/{book_id}/cover")]
 async fn cover(
+    req: HttpRequest,
     bookid: web::Path<BookId>,
+    query: web::Query<CoverQuery>,
     config: web::Data<Config>,
     db: web::Data<DatabaseConnection>,
     AuthData(user): AuthData,
-) -> Option<NamedFile> {
-    let book = bookid.get(user.id, &db).await.unwrap(); // we dont care just fail lol
-    if let Some(meta) = book.meta {
-        if let Some(mime) = meta.cover_mime {
-            let fp = format!("{}/{}-cover.bin", config.filepath, book.hash);
-            Some(
-                NamedFile::open_async(fp)
-                    .await
-                    .unwrap()
-                    .set_content_type(mime.parse().unwrap())
-                    .set_content_disposition(ContentDisposition::attachment(format!(
-                        "{} - {}",
-                        meta.creator, meta.title
-                    ))),
-            )
-        } else {
-            None
+) -> actix_web::Result<HttpResponse> {
+    let book = bookid.get(user.id, &db).await.map_err(not_found)?;
+    let (title, creator) = match &book.meta {
+        Some(meta) => (meta.title.clone(), meta.creator.clone()),
+        None => (book.title.clone(), String::new()),
+    };
+    let disposition = ContentDisposition::attachment(format!("{} - {}", creator, title));
+    let (cover, mime) = match book.meta.and_then(|meta| meta.cover_mime) {
+        Some(mime) if book.custom_cover => (custom_cover_name(&book.hash, book.id), mime),
+        Some(mime) => (format!("{}-cover", book.hash), mime),
+        // no cover in the book, make one up so the client doesn't show a blank tile
+        None => {
+            let (conf, hash) = (config.clone(), book.hash.clone());
+            let name =
+                web::block(move || covers::cached_placeholder(&conf, &hash, &title, &creator))
+                    .await?
+                    .map_err(server_error)?;
+            (name, "image/png".to_string())
         }
-    } else {
-        None
+    };
+    if query.size == covers::Size::Original {
+        return serve_blob(
+            &req,
+            &config,
+            &format!("{cover}.bin"),
+            Some(&mime),
+            disposition,
+        )
+        .await;
+    }
+    let (size, format) = (query.size, query.format);
+    let thumb =
+        web::block(move || covers::cached_thumbnail(&config, &cover, size, format)).await??;
+    Ok(HttpResponse::Ok()
+        .content_type(format.mime())
+        .insert_header(disposition)
+        .body(thumb))
+}
+
+#[put("/book/{book_id}/cover")]
+async fn set_cover(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    MultipartForm(form): MultipartForm<CoverForm>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let mut image = form.image;
+    let mut buf: Vec<u8> = vec![];
+    image.file.read_to_end(&mut buf)?;
+    // don't trust the content type the client sent, check that it's really a picture we can scale
+    let mime = match metadata::image_mime(&buf) {
+        Some(mime) if image::load_from_memory(&buf).is_ok() => mime,
+        _ => {
+            return Err(error::ErrorUnsupportedMediaType(ErrorResponse {
+                status: "error".to_string(),
+                error: "cover has to be a jpeg, png, gif or webp image".to_string(),
+            }))
+        }
+    };
+
+    let name = custom_cover_name(&book.hash, book.id);
+    storage::write(&config, &format!("{name}.bin"), &buf)?;
+    covers::remove_thumbnails(&config, &name)?;
+
+    let mut edits = book_edits(db, &config, &book).await.map_err(db_error)?;
+    edits.cover_mime = ActiveValue::Set(Some(mime.to_string()));
+    edits.save(db).await.map_err(db_error)?;
+    changes::record(db, user.id, book.id, changes::UPDATED)
+        .await
+        .map_err(db_error)?;
+
+    match bookid.get(user.id, db).await {
+        Ok(b) => Ok(ok(b)),
+        Err(e) => Err(not_found(e)),
+    }
+}
+
+#[delete("/book/{book_id}/cover")]
+async fn reset_cover(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+
+    if let Some(edits) = BookOverride::find()
+        .filter(BOCol::BookId.eq(book.id))
+        .one(db)
+        .await
+        .map_err(db_error)?
+    {
+        let mut edits: BOActiveModel = edits.into();
+        edits.cover_mime = ActiveValue::Set(None);
+        edits.save(db).await.map_err(db_error)?;
+    }
+    let name = custom_cover_name(&book.hash, book.id);
+    storage::remove(&config, &format!("{name}.bin"))?;
+    covers::remove_thumbnails(&config, &name)?;
+    changes::record(db, user.id, book.id, changes::UPDATED)
+        .await
+        .map_err(db_error)?;
+
+    match bookid.get(user.id, db).await {
+        Ok(b) => Ok(ok(b)),
+        Err(e) => Err(not_found(e)),
     }
 }
 
 #[delete("/book/{book_id}")]
 async fn remove(
     bookid: web::Path<BookId>,
-    _config: web::Data<Config>,
+    config: web::Data<Config>,
     db: web::Data<DatabaseConnection>,
+    search: web::Data<Search>,
     AuthData(user): AuthData,
 ) -> actix_web::Result<impl actix_web::Responder> {
     let db: &DatabaseConnection = &db;
+    let hash = Book::find_by_id(bookid.book_id)
+        .filter(BookCol::UserId.eq(user.id))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .map(|book| book.hash);
     match Book::delete(BookActiveModel {
         title: ActiveValue::NotSet,
         hash: ActiveValue::NotSet,
         file_tyoe: ActiveValue::NotSet,
+        size: ActiveValue::NotSet,
+        created_at: ActiveValue::NotSet,
+        updated_at: ActiveValue::NotSet,
+        file_name: ActiveValue::NotSet,
+        partial_md5: ActiveValue::NotSet,
         id: ActiveValue::Set(bookid.book_id),
         user_id: ActiveValue::Set(user.id),
     })
     .exec(db)
     .await
     {
-        Ok(b) => Ok(HttpResponse::Ok().json(Response {
-            status: "ok".to_string(),
-            data: b.rows_affected,
-        })),
-        Err(e) => Err(error::ErrorNotFound(ErrorResponse {
-            status: "error".to_string(),
-            error: e.to_string(),
-        })),
+        Ok(b) => {
+            if b.rows_affected > 0 {
+                changes::record(db, user.id, bookid.book_id, changes::DELETED)
+                    .await
+                    .map_err(server_error)?;
+                let (search, id) = (search.clone(), bookid.book_id);
+                if let Err(e) = web::block(move || search.remove(id)).await? {
+                    warn!("Failed to remove book {} from the index: {}", id, e);
+                }
+                // the file itself may be someone else's too, a custom cover is only ever this book's
+                if let Some(hash) = hash {
+                    let name = custom_cover_name(&hash, id);
+                    if let Err(e) = storage::remove(&config, &format!("{name}.bin"))
+                        .and_then(|()| covers::remove_thumbnails(&config, &name))
+                    {
+                        warn!("Failed to remove the cover of book {}: {}", id, e);
+                    }
+                }
+            }
+            Ok(ok(b.rows_affected))
+        }
+        Err(e) => Err(not_found(e)),
     }
 }
 
-#[get("/books")]
-async fn list(
-    _config: web::Data<Config>,
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+    page: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SearchHit {
+    score: f32,
+    book: FullBook,
+    /// matching bits of each field with the hits wrapped in `<b>`
+    snippets: BTreeMap<&'static str, String>,
+}
+
+const MAX_SEARCH_RESULTS: usize = 10_000;
+
+#[get("/books/search")]
+async fn search_books(
+    query: web::Query<SearchQuery>,
     db: web::Data<DatabaseConnection>,
+    search: web::Data<Search>,
     AuthData(user): AuthData,
 ) -> actix_web::Result<impl actix_web::Responder> {
     let db: &DatabaseConnection = &db;
-    match Book::find()
+    let SearchQuery { q, limit, page } = query.into_inner();
+    let limit = limit.unwrap_or(20).clamp(1, 100);
+    // the index has to rank everything up to the page asked for, so don't go arbitrarily deep
+    let offset = page
+        .unwrap_or(0)
+        .checked_mul(limit)
+        .filter(|offset| offset + limit <= MAX_SEARCH_RESULTS)
+        .ok_or_else(|| {
+            bad_request(format!(
+                "Only the first {MAX_SEARCH_RESULTS} results can be paged through."
+            ))
+        })?;
+    let hits = web::block(move || search.search(user.id, &q, limit, offset))
+        .await?
+        .map_err(server_error)?;
+    let books = Book::find()
+        .filter(BookCol::Id.is_in(hits.iter().map(|hit| hit.book_id)))
         .filter(BookCol::UserId.eq(user.id))
+        .find_also_related(FileType)
         .all(db)
         .await
-    {
-        Ok(b) => Ok(HttpResponse::Ok().json(Response {
-            status: "ok".to_string(),
-            data: b,
-        })),
-        Err(e) => Err(error::ErrorNotFound(ErrorResponse {
-            status: "error".to_string(),
-            error: e.to_string(),
-        })),
-    }
+        .map_err(db_error)?;
+    let mut books: HashMap<i32, FullBook> = full_books(db, books)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|book| (book.id, book))
+        .collect();
+    // the index may lag behind the database, skip books that are gone
+    let results: Vec<SearchHit> = hits
+        .into_iter()
+        .filter_map(|hit| {
+            Some(SearchHit {
+                score: hit.score,
+                book: books.remove(&hit.book_id)?,
+                snippets: hit.snippets,
+            })
+        })
+        .collect();
+    Ok(ok(results))
 }
 
-#[put("/book")]
-async fn upload(
-    config: web::Data<Config>,
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    Title,
+    Author,
+    /// upload time, the default
+    Added,
+    /// last edit
+    Updated,
+    Size,
+    /// order within the `collection`, the default when filtering by one
+    Position,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    sort: Option<SortKey>,
+    #[serde(default)]
+    order: SortOrder,
+    file_type: Option<String>,
+    /// part of the author's name
+    author: Option<String>,
+    series: Option<String>,
+    collection: Option<i32>,
+    /// whether books need `all` of the `tag`s or `any` of them
+    #[serde(default)]
+    tag_mode: TagMode,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum TagMode {
+    #[default]
+    All,
+    Any,
+}
+
+/// Lowercases `value` and escapes it for a `LIKE` pattern using `\\` as the escape character.
+pub(crate) fn escape_like(value: &str) -> String {
+    value
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// `LIKE` pattern matching `value` anywhere, case insensitive when compared against `lower(..)`.
+fn contains(value: &str) -> LikeExpr {
+    LikeExpr::new(format!("%{}%", escape_like(value))).escape('\\')
+}
+
+#[get("/books")]
+async fn list(
+    req: HttpRequest,
+    _config: web::Data<Config>,
     db: web::Data<DatabaseConnection>,
     AuthData(user): AuthData,
-    MultipartForm(form): MultipartForm<UploadForm>,
-) -> impl actix_web::Responder {
+    limit: web::Query<LimitQuery>,
+    query: web::Query<ListQuery>,
+) -> actix_web::Result<impl actix_web::Responder> {
     let db: &DatabaseConnection = &db;
-    let mut book: TempFile = form.book;
-    let mut hasher = Sha256::new();
-    let mut buf: Vec<u8> = vec![];
-    match book.file.read_to_end(&mut buf) {
-        Ok(_size) => hasher.update(buf.clone()),
-        Err(e) => return e.to_string(),
-    }
-    let hash = encode(hasher.finalize());
-    let new_path = format!("{}/{}.bin", config.filepath, hash);
+    // what the user sees is their edit if they made one, else what we read from the file
+    let title = Func::coalesce([
+        Expr::col((BookOverride, BOCol::Title)).into(),
+        Expr::col((BookInfo, BICol::Title)).into(),
+        Expr::col((Book, BookCol::Title)).into(),
+    ]);
+    let author = Func::coalesce([
+        Expr::col((BookOverride, BOCol::Creator)).into(),
+        Expr::col((BookInfo, BICol::Creator)).into(),
+    ]);
+    let series = Func::coalesce([
+        Expr::col((BookOverride, BOCol::Series)).into(),
+        Expr::col((BookInfo, BICol::Series)).into(),
+    ]);
 
-    match std::fs::metadata(&new_path) {
-        Ok(_) => println!("File already exists!"),
-        Err(_) => std::fs::File::create(&new_path)
-            .unwrap()
-            .write_all(&buf)
-            .unwrap(),
+    let mut select = Book::find()
+        .filter(BookCol::UserId.eq(user.id))
+        .join(
+            JoinType::LeftJoin,
+            Book::belongs_to(BookInfo)
+                .from(BookCol::Hash)
+                .to(BICol::BookHash)
+                .into(),
+        )
+        .join(JoinType::LeftJoin, BookRelation::BookOverride.def())
+        .find_also_related(FileType);
+    if let Some(file_type) = &query.file_type {
+        select = select.filter(FTCol::Name.eq(file_type.to_lowercase()));
+    }
+    if let Some(name) = &query.author {
+        select = select.filter(Expr::expr(Func::lower(author.clone())).like(contains(name)));
+    }
+    if let Some(collection) = query.collection {
+        select = select
+            .join(JoinType::InnerJoin, BookRelation::CollectionBook.def())
+            .filter(CBCol::CollectionId.eq(collection));
+    }
+    // `tag` can be given several times, which the struct above can't hold
+    let wanted: HashSet<String> =
+        web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map(web::Query::into_inner)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(key, _)| key == "tag")
+            .filter_map(|(_, value)| tag::tag_name(&value))
+            .map(|name| tag::name_key(&name))
+            .collect();
+    if !wanted.is_empty() {
+        let count = wanted.len() as i64;
+        let mut tagged = Query::select()
+            .column((BookTag, BTCol::BookId))
+            .from(BookTag)
+            .inner_join(
+                Tag,
+                Expr::col((Tag, TagCol::Id)).equals((BookTag, BTCol::TagId)),
+            )
+            .and_where(Expr::col((Tag, TagCol::UserId)).eq(user.id))
+            .and_where(Expr::col((Tag, TagCol::NameKey)).is_in(wanted))
+            .to_owned();
+        if let TagMode::All = query.tag_mode {
+            tagged
+                .group_by_col((BookTag, BTCol::BookId))
+                .and_having(Expr::expr(Func::count(Expr::col((BookTag, BTCol::TagId)))).eq(count));
+        }
+        select = select.filter(BookCol::Id.in_subquery(tagged));
+    }
+    if let Some(name) = &query.series {
+        select = select.filter(Expr::expr(Func::lower(series)).eq(name.to_lowercase()));
+    }
+    let order = match query.order {
+        SortOrder::Asc => Order::Asc,
+        SortOrder::Desc => Order::Desc,
     };
-    let filename_string = book.file_name.unwrap_or("unk.epub".to_string());
-    let filename = Path::new(&filename_string);
+    let sort = match (query.sort, query.collection) {
+        (Some(SortKey::Position), None) => SortKey::Added,
+        (Some(sort), _) => sort,
+        (None, Some(_)) => SortKey::Position,
+        (None, None) => SortKey::Added,
+    };
+    select = match sort {
+        SortKey::Title => select.order_by(SimpleExpr::from(Func::lower(title)), order.clone()),
+        SortKey::Author => select.order_by(SimpleExpr::from(Func::lower(author)), order.clone()),
+        SortKey::Added => select.order_by(BookCol::CreatedAt, order.clone()),
+        SortKey::Updated => select.order_by(BookCol::UpdatedAt, order.clone()),
+        SortKey::Size => select.order_by(BookCol::Size, order.clone()),
+        SortKey::Position => select.order_by(CBCol::Position, order.clone()),
+    }
+    // keeps pages stable when the sort key is the same for a bunch of books
+    .order_by(BookCol::Id, order);
 
-    let mut extension = ".unk".to_string();
-    if let Some(ext) = filename.extension() {
-        extension = ext.to_string_lossy().to_string();
+    let page = match limit.limit {
+        Some(per_page) => {
+            let pages = select.paginate(db, per_page.clamp(1, 1000) as u64);
+            match pages.num_items().await {
+                Ok(total) => pages
+                    .fetch_page(limit.page.unwrap_or(0).max(0) as u64)
+                    .await
+                    .map(|b| (b, total)),
+                Err(e) => Err(e),
+            }
+        }
+        None => select.all(db).await.map(|b| {
+            let total = b.len() as u64;
+            (b, total)
+        }),
+    };
+    let page = match page {
+        Ok((b, total)) => full_books(db, b).await.map(|b| (b, total)),
+        Err(e) => Err(e),
+    };
+    match page {
+        Ok((b, total)) => Ok(HttpResponse::Ok().json(Response::page(b, total))),
+        Err(e) => Err(not_found(e)),
     }
+}
 
-    let new_book_info = if BookInfo::find()
-        .filter(BICol::BookHash.eq(&hash))
-        .one(db)
+/// Records a book that just landed in a library, tags it with its subjects and indexes it.
+/// `data` is the file if it's at hand, it only gets read again when the index wants the text.
+async fn added(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &web::Data<Search>,
+    user_id: i32,
+    book_id: i32,
+    data: Option<Vec<u8>>,
+) -> actix_web::Result<()> {
+    changes::record(db, user_id, book_id, changes::CREATED)
         .await
-        .unwrap()
-        .is_none()
-        && extension.to_lowercase() == "epub"
-    {
-        // yo we got an epub - parse that shit
-        if let Ok(mut epub) = EpubDoc::from_reader(Cursor::new(buf)) {
-            let title = epub.mdata("title");
-            let creator = epub.mdata("creator");
-            let mimetype: Option<String> = if let Some((cover_data, mime_type)) = epub.get_cover() {
-                std::fs::File::create(format!("{}/{}-cover.bin", config.filepath, hash))
-                    .unwrap()
-                    .write_all(&cover_data)
-                    .unwrap();
-                Some(mime_type)
-            } else {
-                None
-            };
-            let new_book_info = BookInfoActiveModel {
-                id: ActiveValue::NotSet,
-                book_hash: ActiveValue::Set(hash.clone()),
-                title: ActiveValue::Set(title.unwrap()),
-                creator: ActiveValue::Set(creator.unwrap()),
-                cover_mime: ActiveValue::Set(mimetype),
-            };
-            Some(new_book_info)
-        } else {
-            // maybe it wasnt an epub (?) lol
-            None
+        .map_err(server_error)?;
+    let bookid = BookId { book_id };
+    // subjects make a decent set of tags to start with
+    let subjects = bookid
+        .get(user_id, db)
+        .await
+        .ok()
+        .and_then(|book| book.meta)
+        .and_then(|meta| meta.subjects);
+    if let Some(subjects) = subjects {
+        tag::tag_book(db, user_id, book_id, &subjects.0)
+            .await
+            .map_err(db_error)?;
+    }
+    // fetched after tagging so what gets indexed is the book as it is now, tags included
+    if let Ok(book) = bookid.get(user_id, db).await {
+        let content = match search.indexes_content() && book.file_type.name == "epub" {
+            true => {
+                let data = match data {
+                    Some(data) => data,
+                    None => storage::read(config, &format!("{}.bin", book.hash))?,
+                };
+                web::block(move || metadata::epub::text(&data)).await?
+            }
+            false => None,
+        };
+        reindex(search, &book, content).await;
+    }
+    Ok(())
+}
+
+/// Everything an upload goes through once the file is in memory, wherever it came from.
+/// Returns the id of the new book.
+pub(crate) async fn add_book(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &web::Data<Search>,
+    user_id: i32,
+    file_name: Option<String>,
+    buf: Vec<u8>,
+) -> actix_web::Result<i32> {
+    // some clients send the whole path along, windows ones with backslashes
+    let original_name = file_name
+        .as_deref()
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    let filename_string = file_name.unwrap_or("unk".to_string());
+    let filename = Path::new(&filename_string);
+
+    let extension = filename
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    // hashing and sniffing read the whole file, keep that off the executor
+    let (buf, hash, detected, partial_md5) = web::block(move || {
+        let hash = encode(Sha256::digest(&buf));
+        let detected = detect::detect(&buf);
+        let partial_md5 = kosync::partial_md5(&buf);
+        (buf, hash, detected, partial_md5)
+    })
+    .await?;
+
+    // trust the content, not the name
+    let (ft_name, mime) = match detected {
+        Some(d) if extension.is_empty() => (d.name.to_string(), Some(d.mime)),
+        Some(d) if detect::mismatch(d, &extension) => {
+            return Err(error::ErrorUnsupportedMediaType(ErrorResponse {
+                status: "error".to_string(),
+                error: format!(
+                    "file looks like {} but was uploaded as .{}",
+                    d.name, extension
+                ),
+            }))
         }
-    } else {
-        None
+        // keep what the client called it if it's just another name for the same container
+        Some(d) if detect::extensions(d).contains(&extension.as_str()) => {
+            (extension.clone(), Some(d.mime))
+        }
+        Some(d) => (d.name.to_string(), Some(d.mime)),
+        None if extension.is_empty() => {
+            return Err(error::ErrorUnsupportedMediaType(ErrorResponse {
+                status: "error".to_string(),
+                error: "can't tell what kind of file this is, it has no extension".to_string(),
+            }))
+        }
+        None => (extension.clone(), None),
     };
 
+    let blob_name = format!("{}.bin", hash);
+    let buf = web::block({
+        let config = config.clone();
+        move || {
+            if storage::exists(&config, &blob_name) {
+                debug!("{} is stored already", blob_name);
+            } else {
+                storage::write(&config, &blob_name, &buf)?;
+            }
+            Ok::<_, std::io::Error>(buf)
+        }
+    })
+    .await??;
+
     let mut title = "unk".to_string();
     if let Some(name) = filename.file_stem() {
         title = name.to_string_lossy().to_string();
     }
 
+    let has_book_info = BookInfo::find()
+        .filter(BICol::BookHash.eq(&hash))
+        .one(db)
+        .await
+        .map_err(server_error)?
+        .is_some();
+    let (buf, new_book_info) = match has_book_info {
+        true => (buf, None),
+        false => {
+            let (config, hash, title) = (config.clone(), hash.clone(), title.clone());
+            let format = detected.map_or(ft_name.clone(), |d| d.name.to_string());
+            let (buf, info) = web::block(move || {
+                // even if we can't read anything we still want a row users can put their edits on
+                let meta = metadata::extractor(&format)
+                    .and_then(|ex| ex.extract(&buf))
+                    .unwrap_or_default();
+                let info = meta.into_book_info(&config, &hash, &title);
+                (buf, info)
+            })
+            .await?;
+            (buf, Some(info?))
+        }
+    };
+
     let ft_id = match FileType::find()
-        .filter(FTCol::Name.eq(extension.to_lowercase()))
+        .filter(FTCol::Name.eq(&ft_name))
         .one(db)
         .await
     {
-        Ok(Some(ft)) => ft.id,
+        Ok(Some(ft)) => {
+            // file types created before we sniffed content don't know their mime type yet
+            if ft.mime.is_none() && mime.is_some() {
+                let _ = FileType::update(FTActiveModel {
+                    id: ActiveValue::Unchanged(ft.id),
+                    name: ActiveValue::Unchanged(ft.name),
+                    mime: ActiveValue::Set(mime.map(str::to_string)),
+                })
+                .exec(db)
+                .await;
+            }
+            ft.id
+        }
         Ok(None) => match FileType::insert(FTActiveModel {
             id: ActiveValue::NotSet,
-            name: ActiveValue::Set(extension.to_lowercase()),
+            name: ActiveValue::Set(ft_name),
+            mime: ActiveValue::Set(mime.map(str::to_string)),
         })
         .exec(db)
         .await
@@ -292,19 +1003,135 @@ async fn upload(
         Err(_) => 0,
     };
 
+    let now = Utc::now().timestamp();
     let new_book = BookActiveModel {
         id: ActiveValue::NotSet,
         title: ActiveValue::Set(title),
         hash: ActiveValue::Set(hash),
-        user_id: ActiveValue::Set(user.id),
+        user_id: ActiveValue::Set(user_id),
         file_tyoe: ActiveValue::Set(ft_id),
+        size: ActiveValue::Set(Some(buf.len() as i64)),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        file_name: ActiveValue::Set(original_name),
+        partial_md5: ActiveValue::Set(Some(partial_md5)),
     };
 
-    Book::insert(new_book).exec(db).await.unwrap();
+    let book_id = Book::insert(new_book)
+        .exec(db)
+        .await
+        .map_err(server_error)?
+        .last_insert_id;
     if let Some(nbi) = new_book_info {
-        BookInfo::insert(nbi).exec(db).await.unwrap();
+        BookInfo::insert(nbi).exec(db).await.map_err(server_error)?;
     };
-    "ok".to_string()
+    added(config, db, search, user_id, book_id, Some(buf)).await?;
+    Ok(book_id)
+}
+
+/// Puts someone else's book into `user_id`'s library as their own, on the same blob and
+/// metadata. The owner's edits and tags stay with the owner.
+pub(crate) async fn copy_book(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &web::Data<Search>,
+    user_id: i32,
+    book: BookModel,
+) -> actix_web::Result<i32> {
+    let now = Utc::now().timestamp();
+    let book_id = Book::insert(BookActiveModel {
+        id: ActiveValue::NotSet,
+        title: ActiveValue::Set(book.title),
+        hash: ActiveValue::Set(book.hash),
+        user_id: ActiveValue::Set(user_id),
+        file_tyoe: ActiveValue::Set(book.file_tyoe),
+        size: ActiveValue::Set(book.size),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        file_name: ActiveValue::Set(book.file_name),
+        partial_md5: ActiveValue::Set(book.partial_md5),
+    })
+    .exec(db)
+    .await
+    .map_err(server_error)?
+    .last_insert_id;
+    added(config, db, search, user_id, book_id, None).await?;
+    Ok(book_id)
+}
+
+#[put("/book")]
+async fn upload(
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    search: web::Data<Search>,
+    AuthData(user): AuthData,
+    MultipartForm(form): MultipartForm<UploadForm>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let mut book: TempFile = form.book;
+    let mut buf: Vec<u8> = vec![];
+    if let Err(e) = book.file.read_to_end(&mut buf) {
+        return Err(bad_request(e));
+    }
+    add_book(&config, &db, &search, user.id, book.file_name, buf).await?;
+    Ok("ok".to_string())
+}
+
+/// Fills in the size of books uploaded before we stored it.
+pub async fn backfill_sizes(config: &Config, db: &DatabaseConnection) -> Result<(), DbErr> {
+    let books = Book::find().filter(BookCol::Size.is_null()).all(db).await?;
+    for book in books {
+        match storage::size(config, &format!("{}.bin", book.hash)) {
+            Ok(size) => {
+                let mut book: BookActiveModel = book.into();
+                book.size = ActiveValue::Set(Some(size as i64));
+                book.update(db).await?;
+            }
+            Err(e) => warn!("Couldn't get the size of {}: {}", book.hash, e),
+        }
+    }
+    Ok(())
+}
+
+/// Dates books from before we kept track by when their file was stored.
+pub async fn backfill_timestamps(config: &Config, db: &DatabaseConnection) -> Result<(), DbErr> {
+    let books = Book::find()
+        .filter(BookCol::CreatedAt.eq(0))
+        .all(db)
+        .await?;
+    for book in books {
+        let stored = std::fs::metadata(storage::path(config, &format!("{}.bin", book.hash)))
+            .and_then(|meta| meta.modified());
+        match stored {
+            Ok(time) => {
+                let time = chrono::DateTime::<Utc>::from(time).timestamp();
+                let mut book: BookActiveModel = book.into();
+                book.created_at = ActiveValue::Set(time);
+                book.updated_at = ActiveValue::Set(time);
+                book.update(db).await?;
+            }
+            Err(e) => warn!("Couldn't tell when {} was stored: {}", book.hash, e),
+        }
+    }
+    Ok(())
+}
+
+/// Works out KOReader's document hash for books uploaded before kosync support.
+pub async fn backfill_partial_md5s(config: &Config, db: &DatabaseConnection) -> Result<(), DbErr> {
+    let books = Book::find()
+        .filter(BookCol::PartialMd5.is_null())
+        .all(db)
+        .await?;
+    for book in books {
+        match storage::read(config, &format!("{}.bin", book.hash)) {
+            Ok(data) => {
+                let mut book: BookActiveModel = book.into();
+                book.partial_md5 = ActiveValue::Set(Some(kosync::partial_md5(&data)));
+                book.update(db).await?;
+            }
+            Err(e) => warn!("Couldn't read {} to hash it for kosync: {}", book.hash, e),
+        }
+    }
+    Ok(())
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -312,6 +1139,129 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(download)
         .service(remove)
         .service(list)
+        .service(search_books)
         .service(book_info)
-        .service(cover);
+        .service(edit)
+        .service(cover)
+        .service(set_cover)
+        .service(reset_cover);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::test;
+
+    const PDF: &[u8] = b"%PDF-1.4\n%%EOF\n";
+
+    #[actix_web::test]
+    async fn download_is_named_after_the_edited_title() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let id = add_book(
+            &config,
+            &db,
+            &search,
+            user.id,
+            Some("scan_0042.pdf".into()),
+            PDF.into(),
+        )
+        .await
+        .unwrap();
+
+        let disposition = |res: actix_web::dev::ServiceResponse| {
+            res.headers()
+                .get("content-disposition")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        let req = test::TestRequest::get().uri(&format!("/api/book/{id}/dl"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(disposition(res).contains("\"scan_0042.pdf\""));
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/book/{id}"))
+            .set_json(serde_json::json!({ "title": "The Real Title" }));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(res.status().is_success());
+        let req = test::TestRequest::get().uri(&format!("/api/book/{id}/dl"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(disposition(res).contains("\"The Real Title.pdf\""));
+    }
+
+    #[actix_web::test]
+    async fn removing_a_book_removes_its_cover() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let id = add_book(
+            &config,
+            &db,
+            &search,
+            user.id,
+            Some("a.pdf".into()),
+            PDF.into(),
+        )
+        .await
+        .unwrap();
+        let book = (BookId { book_id: id }).get(user.id, &db).await.unwrap();
+        let cover_name = custom_cover_name(&book.hash, id);
+        let thumbnail =
+            covers::thumbnail_name(&cover_name, covers::Size::Small, covers::Format::Jpeg);
+        storage::write(&config, &format!("{cover_name}.bin"), b"cover").unwrap();
+        storage::write(&config, &thumbnail, b"thumbnail").unwrap();
+
+        let req = test::TestRequest::delete().uri(&format!("/api/book/{id}"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(res.status().is_success());
+        assert!(!storage::exists(&config, &format!("{cover_name}.bin")));
+        assert!(!storage::exists(&config, &thumbnail));
+        // the book's file stays, other books might be using it
+        assert!(storage::exists(&config, &format!("{}.bin", book.hash)));
+    }
+
+    #[actix_web::test]
+    async fn search_skips_books_the_index_still_has() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let mut ids = vec![];
+        for name in ["Dune Messiah.pdf", "Children of Dune.pdf", "Emma.pdf"] {
+            // different content, otherwise they'd share their metadata
+            let pdf = format!("%PDF-1.4\n% {name}\n%%EOF\n");
+            let id = add_book(
+                &config,
+                &db,
+                &search,
+                user.id,
+                Some(name.into()),
+                pdf.into(),
+            )
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+        // gone from the database without the index hearing about it
+        Book::delete_by_id(ids[1]).exec(&db).await.unwrap();
+
+        let req = test::TestRequest::get().uri("/api/books/search?q=dune");
+        let res: serde_json::Value = test::call_and_read_body_json(&app, req.to_request()).await;
+        let found: Vec<i64> = res["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["book"]["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(found, [i64::from(ids[0])]);
+    }
 }
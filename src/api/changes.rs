@@ -0,0 +1,233 @@
+use super::book::{full_books, FullBook};
+use super::{db_error, ok};
+use crate::AuthData;
+use actix_web::{get, web};
+use chrono::Utc;
+use entity::book::Column as BookCol;
+use entity::book_change::ActiveModel as BCActiveModel;
+use entity::book_change::Column as BCCol;
+use entity::prelude::{Book, BookChange, FileType};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const CREATED: &str = "created";
+pub const UPDATED: &str = "updated";
+pub const DELETED: &str = "deleted";
+
+/// Adds an entry to the user's change feed, updates also bump the book's `updated_at`.
+pub async fn record(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+    kind: &str,
+) -> Result<(), DbErr> {
+    let now = Utc::now().timestamp();
+    if kind == UPDATED {
+        Book::update_many()
+            .col_expr(BookCol::UpdatedAt, Expr::value(now))
+            .filter(BookCol::Id.eq(book_id))
+            .exec(db)
+            .await?;
+    }
+    BookChange::insert(BCActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        book_id: ActiveValue::Set(book_id),
+        kind: ActiveValue::Set(kind.to_string()),
+        changed_at: ActiveValue::Set(now),
+    })
+    .exec(db)
+    .await
+    .map(|_| ())
+}
+
+#[derive(Deserialize)]
+struct ChangesQuery {
+    /// `cursor` from the last sync, leave it out to get everything
+    #[serde(default)]
+    since: i32,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Change {
+    book_id: i32,
+    kind: String,
+    changed_at: i64,
+    /// what the book looks like now, missing for deleted books
+    #[serde(skip_serializing_if = "Option::is_none")]
+    book: Option<FullBook>,
+}
+
+#[derive(Serialize)]
+struct Changes {
+    /// pass this as `since` next time
+    cursor: i32,
+    /// there were more changes than `limit`, ask again with the new cursor
+    more: bool,
+    changes: Vec<Change>,
+}
+
+#[get("/books/changes")]
+async fn changes(
+    query: web::Query<ChangesQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let limit = query.limit.unwrap_or(500).clamp(1, 5000);
+    let log = BookChange::find()
+        .filter(BCCol::UserId.eq(user.id))
+        .filter(BCCol::Id.gt(query.since))
+        .order_by_asc(BCCol::Id)
+        .limit(limit)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let cursor = log.last().map_or(query.since, |c| c.id);
+    let more = log.len() as u64 == limit;
+
+    // a client only cares about where each book ended up, not every step on the way
+    let mut latest: Vec<Change> = vec![];
+    let mut seen: HashMap<i32, usize> = HashMap::new();
+    for entry in log {
+        match seen.get(&entry.book_id) {
+            Some(&i) => {
+                let change = &mut latest[i];
+                // something created in this batch is still new to the client after edits
+                if entry.kind != UPDATED || change.kind != CREATED {
+                    change.kind = entry.kind;
+                }
+                change.changed_at = entry.changed_at;
+            }
+            None => {
+                seen.insert(entry.book_id, latest.len());
+                latest.push(Change {
+                    book_id: entry.book_id,
+                    kind: entry.kind,
+                    changed_at: entry.changed_at,
+                    book: None,
+                });
+            }
+        }
+    }
+
+    let alive = Book::find()
+        .filter(BookCol::UserId.eq(user.id))
+        .filter(
+            BookCol::Id.is_in(
+                latest
+                    .iter()
+                    .filter(|c| c.kind != DELETED)
+                    .map(|c| c.book_id),
+            ),
+        )
+        .find_also_related(FileType)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut books: HashMap<i32, FullBook> = full_books(db, alive)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|b| (b.id, b))
+        .collect();
+    let changes = latest
+        .into_iter()
+        .filter_map(|mut change| {
+            if change.kind != DELETED {
+                // gone by now, its tombstone comes with a later cursor
+                change.book = Some(books.remove(&change.book_id)?);
+            }
+            Some(change)
+        })
+        .collect();
+
+    Ok(ok(Changes {
+        cursor,
+        more,
+        changes,
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(changes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use serde_json::Value;
+
+    fn kinds(feed: &Value) -> Vec<(i64, &str)> {
+        feed["data"]["changes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| (c["book_id"].as_i64().unwrap(), c["kind"].as_str().unwrap()))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn feed_folds_changes_per_book() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let mut ids = vec![];
+        for name in ["a.pdf", "b.pdf"] {
+            let pdf = format!("%PDF-1.4\n% {name}\n%%EOF\n");
+            ids.push(
+                add_book(
+                    &config,
+                    &db,
+                    &search,
+                    user.id,
+                    Some(name.into()),
+                    pdf.into(),
+                )
+                .await
+                .unwrap() as i64,
+            );
+        }
+        record(&db, user.id, ids[0] as i32, UPDATED).await.unwrap();
+
+        // edited right after the upload is still just new to the client
+        let first = testing::get_json(&app, "/api/books/changes").await;
+        assert_eq!(kinds(&first), [(ids[0], "created"), (ids[1], "created")]);
+        assert_eq!(first["data"]["more"], false);
+        assert_eq!(first["data"]["changes"][0]["book"]["id"], ids[0]);
+
+        Book::delete_by_id(ids[0] as i32).exec(&db).await.unwrap();
+        record(&db, user.id, ids[0] as i32, DELETED).await.unwrap();
+        record(&db, user.id, ids[1] as i32, UPDATED).await.unwrap();
+        let cursor = &first["data"]["cursor"];
+        let second = testing::get_json(&app, &format!("/api/books/changes?since={cursor}")).await;
+        assert_eq!(kinds(&second), [(ids[0], "deleted"), (ids[1], "updated")]);
+        assert!(second["data"]["changes"][0].get("book").is_none());
+
+        // nothing new since the last cursor
+        let cursor = &second["data"]["cursor"];
+        let third = testing::get_json(&app, &format!("/api/books/changes?since={cursor}")).await;
+        assert_eq!(kinds(&third), []);
+        assert_eq!(&third["data"]["cursor"], cursor);
+
+        // one at a time the first entry is the upload of a book that's gone by now,
+        // it's left out and the client learns about it from the tombstone later on
+        let paged = testing::get_json(&app, "/api/books/changes?limit=1").await;
+        assert_eq!(paged["data"]["more"], true);
+        assert_eq!(kinds(&paged), []);
+        let cursor = &paged["data"]["cursor"];
+        let uri = format!("/api/books/changes?limit=1&since={cursor}");
+        let paged = testing::get_json(&app, &uri).await;
+        assert_eq!(kinds(&paged), [(ids[1], "created")]);
+    }
+}
@@ -0,0 +1,316 @@
+use super::book::{full_books, FullBook};
+use super::{bad_request, db_error, not_found, ok};
+use crate::AuthData;
+use actix_web::web;
+use actix_web::{delete, get, patch, put};
+use entity::book::Column as BookCol;
+use entity::book::Relation as BookRelation;
+use entity::collection::ActiveModel as CActiveModel;
+use entity::collection::Column as CCol;
+use entity::collection::Model as CModel;
+use entity::collection_book::ActiveModel as CBActiveModel;
+use entity::collection_book::Column as CBCol;
+use entity::prelude::{Book, Collection, CollectionBook, FileType};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+struct CollectionId {
+    collection_id: i32,
+}
+
+#[derive(Deserialize)]
+struct CollectionBookId {
+    collection_id: i32,
+    book_id: i32,
+}
+
+#[derive(Deserialize)]
+struct CollectionRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct BookIds {
+    book_ids: Vec<i32>,
+}
+
+#[derive(Serialize)]
+struct CollectionSummary {
+    #[serde(flatten)]
+    collection: CModel,
+    book_count: i64,
+}
+
+#[derive(Serialize)]
+struct FullCollection {
+    #[serde(flatten)]
+    collection: CModel,
+    /// in collection order
+    books: Vec<FullBook>,
+}
+
+/// Trims the name and refuses empty ones.
+fn collection_name(req: CollectionRequest) -> actix_web::Result<String> {
+    let name = req.name.trim();
+    match name.is_empty() {
+        true => Err(bad_request("Collection name can't be empty.")),
+        false => Ok(name.to_string()),
+    }
+}
+
+/// Loads one of the user's collections, 404s for anyone else's.
+async fn find(db: &DatabaseConnection, uid: i32, id: i32) -> actix_web::Result<CModel> {
+    match Collection::find_by_id(id)
+        .filter(CCol::UserId.eq(uid))
+        .one(db)
+        .await
+    {
+        Ok(Some(c)) => Ok(c),
+        Ok(None) => Err(not_found("No such collection found.")),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+async fn full_collection(
+    db: &DatabaseConnection,
+    collection: CModel,
+) -> Result<FullCollection, DbErr> {
+    let books = Book::find()
+        .join(JoinType::InnerJoin, BookRelation::CollectionBook.def())
+        .filter(CBCol::CollectionId.eq(collection.id))
+        .order_by_asc(CBCol::Position)
+        .find_also_related(FileType)
+        .all(db)
+        .await?;
+    Ok(FullCollection {
+        collection,
+        books: full_books(db, books).await?,
+    })
+}
+
+#[get("/collections")]
+async fn list(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collections = Collection::find()
+        .filter(CCol::UserId.eq(user.id))
+        .order_by_asc(CCol::Name)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let counts: HashMap<i32, i64> = CollectionBook::find()
+        .select_only()
+        .column(CBCol::CollectionId)
+        .column_as(CBCol::Id.count(), "count")
+        .filter(CBCol::CollectionId.is_in(collections.iter().map(|c| c.id)))
+        .group_by(CBCol::CollectionId)
+        .into_tuple::<(i32, i64)>()
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .collect();
+    Ok(ok(collections
+        .into_iter()
+        .map(|collection| CollectionSummary {
+            book_count: counts.get(&collection.id).copied().unwrap_or(0),
+            collection,
+        })
+        .collect::<Vec<_>>()))
+}
+
+#[put("/collections")]
+async fn create(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<CollectionRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collection = CActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user.id),
+        name: ActiveValue::Set(collection_name(req_data.into_inner())?),
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)?;
+    Ok(ok(collection))
+}
+
+#[get("/collections/{collection_id}")]
+async fn get(
+    path: web::Path<CollectionId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collection = find(db, user.id, path.collection_id).await?;
+    Ok(ok(full_collection(db, collection)
+        .await
+        .map_err(db_error)?))
+}
+
+#[patch("/collections/{collection_id}")]
+async fn rename(
+    path: web::Path<CollectionId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<CollectionRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let mut collection: CActiveModel = find(db, user.id, path.collection_id).await?.into();
+    collection.name = ActiveValue::Set(collection_name(req_data.into_inner())?);
+    Ok(ok(collection.update(db).await.map_err(db_error)?))
+}
+
+#[delete("/collections/{collection_id}")]
+async fn remove(
+    path: web::Path<CollectionId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    // the books stay, only the shelf goes
+    let res = Collection::delete_many()
+        .filter(CCol::Id.eq(path.collection_id))
+        .filter(CCol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+#[put("/collections/{collection_id}/books")]
+async fn add_books(
+    path: web::Path<CollectionId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<BookIds>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collection = find(db, user.id, path.collection_id).await?;
+    let owned: HashSet<i32> = Book::find()
+        .select_only()
+        .column(BookCol::Id)
+        .filter(BookCol::UserId.eq(user.id))
+        .filter(BookCol::Id.is_in(req_data.book_ids.clone()))
+        .into_tuple::<i32>()
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .collect();
+    if let Some(missing) = req_data.book_ids.iter().find(|id| !owned.contains(id)) {
+        return Err(not_found(format!("No book with id {} found.", missing)));
+    }
+
+    let members = CollectionBook::find()
+        .filter(CBCol::CollectionId.eq(collection.id))
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut position = members.iter().map(|m| m.position).max().unwrap_or(-1);
+    let mut present: HashSet<i32> = members.iter().map(|m| m.book_id).collect();
+    // new books go to the end in the order they were sent, ones already there stay put
+    let new: Vec<CBActiveModel> = req_data
+        .book_ids
+        .iter()
+        .filter(|id| present.insert(**id))
+        .map(|id| {
+            position += 1;
+            CBActiveModel {
+                id: ActiveValue::NotSet,
+                collection_id: ActiveValue::Set(collection.id),
+                book_id: ActiveValue::Set(*id),
+                position: ActiveValue::Set(position),
+            }
+        })
+        .collect();
+    if !new.is_empty() {
+        CollectionBook::insert_many(new)
+            .exec(db)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(ok(full_collection(db, collection)
+        .await
+        .map_err(db_error)?))
+}
+
+#[patch("/collections/{collection_id}/books")]
+async fn reorder(
+    path: web::Path<CollectionId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<BookIds>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collection = find(db, user.id, path.collection_id).await?;
+    let members: HashSet<i32> = CollectionBook::find()
+        .filter(CBCol::CollectionId.eq(collection.id))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|m| m.book_id)
+        .collect();
+    let order: HashSet<i32> = req_data.book_ids.iter().copied().collect();
+    if order != members || order.len() != req_data.book_ids.len() {
+        return Err(bad_request(
+            "book_ids has to list every book in the collection exactly once.",
+        ));
+    }
+
+    let txn = db.begin().await.map_err(db_error)?;
+    for (position, book_id) in req_data.book_ids.iter().enumerate() {
+        CollectionBook::update_many()
+            .col_expr(CBCol::Position, Expr::value(position as i32))
+            .filter(CBCol::CollectionId.eq(collection.id))
+            .filter(CBCol::BookId.eq(*book_id))
+            .exec(&txn)
+            .await
+            .map_err(db_error)?;
+    }
+    txn.commit().await.map_err(db_error)?;
+    Ok(ok(full_collection(db, collection)
+        .await
+        .map_err(db_error)?))
+}
+
+#[delete("/collections/{collection_id}/books/{book_id}")]
+async fn remove_book(
+    path: web::Path<CollectionBookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let collection = find(db, user.id, path.collection_id).await?;
+    CollectionBook::delete_many()
+        .filter(CBCol::CollectionId.eq(collection.id))
+        .filter(CBCol::BookId.eq(path.book_id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(full_collection(db, collection)
+        .await
+        .map_err(db_error)?))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(create)
+        .service(get)
+        .service(rename)
+        .service(remove)
+        .service(add_books)
+        .service(reorder)
+        .service(remove_book);
+}
@@ -0,0 +1,335 @@
+use super::book::{full_books, BookId, FullBook};
+use super::{bad_request, db_error, not_found, ok};
+use crate::AuthData;
+use actix_web::{delete, get, post, put, web};
+use chrono::Utc;
+use entity::book::Column as BookCol;
+use entity::delivery::ActiveModel as DeliveryActiveModel;
+use entity::delivery::Column as DeliveryCol;
+use entity::delivery::Model as DeliveryModel;
+use entity::device::ActiveModel as DeviceActiveModel;
+use entity::device::Column as DeviceCol;
+use entity::device::Model as DeviceModel;
+use entity::prelude::{Book, Delivery, Device, FileType};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    /// waiting for the device to ask for it
+    Queued,
+    /// the device saw it in its queue but hasn't confirmed the download yet
+    Fetched,
+    Delivered,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Fetched => "fetched",
+            Status::Delivered => "delivered",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DeviceId {
+    device_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DeliveryPath {
+    device_id: i32,
+    delivery_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DeliveryId {
+    delivery_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DeviceRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DeliverRequest {
+    device_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DeliveryQuery {
+    device_id: Option<i32>,
+    status: Option<Status>,
+}
+
+/// What a device gets out of its queue, everything it needs to download and file the book.
+#[derive(Serialize)]
+struct QueueItem {
+    delivery_id: i32,
+    queued_at: i64,
+    book: FullBook,
+}
+
+async fn find_device(
+    db: &DatabaseConnection,
+    user_id: i32,
+    device_id: i32,
+) -> actix_web::Result<DeviceModel> {
+    Device::find_by_id(device_id)
+        .filter(DeviceCol::UserId.eq(user_id))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found("No such device found.".to_string()))
+}
+
+#[get("/devices")]
+async fn list(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let devices = Device::find()
+        .filter(DeviceCol::UserId.eq(user.id))
+        .order_by_asc(DeviceCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(devices))
+}
+
+/// Registering a name that's already there hands back that device, so a plugin can just do
+/// this on every start.
+#[put("/devices")]
+async fn register(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<DeviceRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let name = req_data.name.trim();
+    if name.is_empty() {
+        return Err(bad_request("Devices need a name."));
+    }
+    let known = Device::find()
+        .filter(DeviceCol::UserId.eq(user.id))
+        .filter(DeviceCol::Name.eq(name))
+        .one(db)
+        .await
+        .map_err(db_error)?;
+    if let Some(device) = known {
+        return Ok(ok(device));
+    }
+    let device = DeviceActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user.id),
+        name: ActiveValue::Set(name.to_string()),
+        created_at: ActiveValue::Set(Utc::now().timestamp()),
+        last_seen_at: ActiveValue::Set(None),
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)?;
+    Ok(ok(device))
+}
+
+#[delete("/devices/{device_id}")]
+async fn remove(
+    path: web::Path<DeviceId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = Device::delete_many()
+        .filter(DeviceCol::Id.eq(path.device_id))
+        .filter(DeviceCol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+/// Puts a book in a device's outbox, or hands back the delivery that's already on its way.
+#[post("/book/{book_id}/deliver")]
+async fn deliver(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<DeliverRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let device = find_device(db, user.id, req_data.device_id).await?;
+    let pending = Delivery::find()
+        .filter(DeliveryCol::DeviceId.eq(device.id))
+        .filter(DeliveryCol::BookId.eq(book.id))
+        .filter(DeliveryCol::Status.ne(Status::Delivered.as_str()))
+        .one(db)
+        .await
+        .map_err(db_error)?;
+    if let Some(delivery) = pending {
+        return Ok(ok(delivery));
+    }
+    let delivery = DeliveryActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user.id),
+        device_id: ActiveValue::Set(device.id),
+        book_id: ActiveValue::Set(book.id),
+        status: ActiveValue::Set(Status::Queued.as_str().to_string()),
+        created_at: ActiveValue::Set(Utc::now().timestamp()),
+        fetched_at: ActiveValue::Set(None),
+        delivered_at: ActiveValue::Set(None),
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)?;
+    Ok(ok(delivery))
+}
+
+/// How the user's deliveries are getting on, newest first.
+#[get("/deliveries")]
+async fn deliveries(
+    query: web::Query<DeliveryQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let mut select = Delivery::find().filter(DeliveryCol::UserId.eq(user.id));
+    if let Some(device_id) = query.device_id {
+        select = select.filter(DeliveryCol::DeviceId.eq(device_id));
+    }
+    if let Some(status) = query.status {
+        select = select.filter(DeliveryCol::Status.eq(status.as_str()));
+    }
+    let deliveries: Vec<DeliveryModel> = select
+        .order_by_desc(DeliveryCol::CreatedAt)
+        .order_by_desc(DeliveryCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(deliveries))
+}
+
+/// Takes a book back out of the outbox.
+#[delete("/deliveries/{delivery_id}")]
+async fn cancel(
+    path: web::Path<DeliveryId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = Delivery::delete_many()
+        .filter(DeliveryCol::Id.eq(path.delivery_id))
+        .filter(DeliveryCol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+/// What the device still has to download. Books stay in here until they're acknowledged, so a
+/// download that failed halfway just gets picked up again next time.
+#[get("/devices/{device_id}/queue")]
+async fn queue(
+    path: web::Path<DeviceId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let device = find_device(db, user.id, path.device_id).await?;
+    let now = Utc::now().timestamp();
+    let pending = Delivery::find()
+        .filter(DeliveryCol::DeviceId.eq(device.id))
+        .filter(DeliveryCol::Status.ne(Status::Delivered.as_str()))
+        .order_by_asc(DeliveryCol::CreatedAt)
+        .order_by_asc(DeliveryCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    Delivery::update_many()
+        .col_expr(
+            DeliveryCol::Status,
+            Status::Fetched.as_str().to_string().into(),
+        )
+        .col_expr(DeliveryCol::FetchedAt, now.into())
+        .filter(DeliveryCol::Id.is_in(pending.iter().map(|d| d.id)))
+        .filter(DeliveryCol::Status.eq(Status::Queued.as_str()))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    let mut device: DeviceActiveModel = device.into();
+    device.last_seen_at = ActiveValue::Set(Some(now));
+    device.update(db).await.map_err(db_error)?;
+
+    let books = Book::find()
+        .filter(BookCol::UserId.eq(user.id))
+        .filter(BookCol::Id.is_in(pending.iter().map(|d| d.book_id)))
+        .find_also_related(FileType)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut books: HashMap<i32, FullBook> = full_books(db, books)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|b| (b.id, b))
+        .collect();
+    let items: Vec<QueueItem> = pending
+        .into_iter()
+        .filter_map(|d| {
+            books.remove(&d.book_id).map(|book| QueueItem {
+                delivery_id: d.id,
+                queued_at: d.created_at,
+                book,
+            })
+        })
+        .collect();
+    Ok(ok(items))
+}
+
+/// The device has the book, it's off the queue.
+#[post("/devices/{device_id}/queue/{delivery_id}/ack")]
+async fn ack(
+    path: web::Path<DeliveryPath>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let delivery = Delivery::find_by_id(path.delivery_id)
+        .filter(DeliveryCol::DeviceId.eq(path.device_id))
+        .filter(DeliveryCol::UserId.eq(user.id))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found("No such delivery found.".to_string()))?;
+    if delivery.status == Status::Delivered.as_str() {
+        return Ok(ok(delivery));
+    }
+    let now = Utc::now().timestamp();
+    // acknowledged straight from the list without pulling the queue first
+    let fetched_at = delivery.fetched_at.unwrap_or(now);
+    let mut delivery: DeliveryActiveModel = delivery.into();
+    delivery.fetched_at = ActiveValue::Set(Some(fetched_at));
+    delivery.status = ActiveValue::Set(Status::Delivered.as_str().to_string());
+    delivery.delivered_at = ActiveValue::Set(Some(now));
+    Ok(ok(delivery.update(db).await.map_err(db_error)?))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(register)
+        .service(remove)
+        .service(deliver)
+        .service(deliveries)
+        .service(cancel)
+        .service(queue)
+        .service(ack);
+}
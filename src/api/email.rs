@@ -0,0 +1,509 @@
+use super::book::BookId;
+use super::{bad_request, db_error, not_found, ok};
+use crate::config::Config;
+use crate::mail::{self, MailError};
+use crate::{storage, AuthData, ErrorResponse};
+use actix_web::{delete, error, get, post, put, web, HttpResponse};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::Utc;
+use entity::email::ActiveModel as EmailActiveModel;
+use entity::email::Column as EmailCol;
+use entity::email::Model as EmailModel;
+use entity::prelude::Email;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+#[derive(Deserialize)]
+struct EmailId {
+    email_id: i32,
+}
+
+#[derive(Deserialize)]
+struct EmailRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct VerifyQuery {
+    email_id: i32,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct CodeRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Default)]
+struct SendRequest {
+    /// every verified address if left out
+    email_id: Option<i32>,
+}
+
+fn mail_error(e: MailError) -> actix_web::Error {
+    let body = ErrorResponse {
+        status: "error".to_string(),
+        error: e.to_string(),
+    };
+    match e {
+        MailError::NotConfigured => error::ErrorNotImplemented(body),
+        MailError::Address(_) => error::ErrorBadRequest(body),
+        MailError::Message(_) => error::ErrorInternalServerError(body),
+        MailError::Smtp(_) => error::ErrorBadGateway(body),
+    }
+}
+
+async fn find(db: &DatabaseConnection, uid: i32, id: i32) -> actix_web::Result<EmailModel> {
+    match Email::find_by_id(id)
+        .filter(EmailCol::UserId.eq(uid))
+        .one(db)
+        .await
+    {
+        Ok(Some(e)) => Ok(e),
+        Ok(None) => Err(not_found("No such email address found.".to_string())),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+/// How long a verification code works, in seconds.
+const VERIFICATION_VALID_FOR: i64 = 24 * 60 * 60;
+
+/// How long an address has to wait for another verification mail, in seconds.
+const RESEND_AFTER: i64 = 5 * 60;
+
+/// When each address last got a verification mail. Kept outside the database so removing an
+/// address and adding it again, or adding it to another account, doesn't get around the wait.
+static LAST_SENT: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
+
+/// Notes that `address` gets a verification mail at `now`, unless it got one too recently, so
+/// nobody can use us to flood someone else's inbox.
+fn claim_send(address: &str, now: i64) -> actix_web::Result<()> {
+    let mut sent = LAST_SENT.lock().unwrap_or_else(PoisonError::into_inner);
+    sent.retain(|_, at| *at + RESEND_AFTER > now);
+    let address = address.to_lowercase();
+    if let Some(at) = sent.get(&address) {
+        return Err(error::ErrorTooManyRequests(ErrorResponse {
+            status: "error".to_string(),
+            error: format!(
+                "A code was just sent there, try again in {} seconds.",
+                at + RESEND_AFTER - now
+            ),
+        }));
+    }
+    sent.insert(address, now);
+    Ok(())
+}
+
+/// Short enough to type in from a kindle, without letters that look like digits.
+fn verification_code() -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+        .iter()
+        .map(|b| ALPHABET[*b as usize % ALPHABET.len()] as char)
+        .collect()
+}
+
+/// Gives the address a new code and mails it there, so the user can prove it is theirs. Kindle
+/// addresses don't show mails, only attachments, so the code comes along as a document too.
+async fn send_verification(
+    config: &Config,
+    db: &DatabaseConnection,
+    username: &str,
+    email: EmailModel,
+) -> actix_web::Result<EmailModel> {
+    claim_send(&email.email, Utc::now().timestamp())?;
+    let code = verification_code();
+    let mut email: EmailActiveModel = email.into();
+    email.verification_token = ActiveValue::Set(Some(code.clone()));
+    email.verification_expires_at =
+        ActiveValue::Set(Some(Utc::now().timestamp() + VERIFICATION_VALID_FOR));
+    let email = email.update(db).await.map_err(db_error)?;
+    // the Host header is up to whoever asked, so only ever link to the configured address
+    let link = match &config.public_url {
+        Some(url) => format!(
+            "Open this link to start getting books there:\n{}/na/email/verify?email_id={}&token={}\n\n\
+             or enter",
+            url.trim_end_matches('/'),
+            email.id,
+            code
+        ),
+        None => "Enter".to_string(),
+    };
+    let text = format!(
+        "Someone (hopefully you) added {} to the stoka account {}.\n\n\
+         {} this code in your client: {}\n\
+         It works for a day.\n\n\
+         If it wasn't you, just ignore this.\n",
+        email.email, username, link, code
+    );
+    mail::send(
+        config,
+        &email.email,
+        "Confirm your address for stoka",
+        text.clone(),
+        Some(mail::File {
+            name: "stoka verification.txt".to_string(),
+            mime: Some("text/plain".to_string()),
+            data: text.into_bytes(),
+        }),
+    )
+    .await
+    .map_err(|e| {
+        // nothing arrived, so trying again right away is fine
+        LAST_SENT
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&email.email.to_lowercase());
+        mail_error(e)
+    })?;
+    Ok(email)
+}
+
+/// Marks the address as verified if `code` is the one that was mailed there and still good.
+async fn verify_code(
+    db: &DatabaseConnection,
+    email_id: i32,
+    code: &str,
+) -> actix_web::Result<EmailModel> {
+    let email = Email::find_by_id(email_id)
+        .filter(EmailCol::VerificationToken.eq(code.trim().to_uppercase()))
+        .filter(EmailCol::VerificationExpiresAt.gt(Utc::now().timestamp()))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found("That code isn't valid (anymore).".to_string()))?;
+    let mut email: EmailActiveModel = email.into();
+    email.verified_at = ActiveValue::Set(Some(Utc::now().timestamp()));
+    email.verification_token = ActiveValue::Set(None);
+    email.verification_expires_at = ActiveValue::Set(None);
+    email.update(db).await.map_err(db_error)
+}
+
+#[get("/user/@me/emails")]
+async fn list(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let emails = Email::find()
+        .filter(EmailCol::UserId.eq(user.id))
+        .order_by_asc(EmailCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(emails))
+}
+
+#[put("/user/@me/emails")]
+async fn add(
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<EmailRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let address = req_data.email.trim();
+    if !mail::is_address(address) {
+        return Err(bad_request(format!("{} isn't an email address.", address)));
+    }
+    let taken = Email::find()
+        .filter(EmailCol::UserId.eq(user.id))
+        .filter(EmailCol::Email.eq(address))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .is_some();
+    if taken {
+        return Err(error::ErrorConflict(ErrorResponse {
+            status: "error".to_string(),
+            error: "You already added that address.".to_string(),
+        }));
+    }
+    let email = EmailActiveModel {
+        id: ActiveValue::NotSet,
+        email: ActiveValue::Set(address.to_string()),
+        user_id: ActiveValue::Set(user.id),
+        verified_at: ActiveValue::Set(None),
+        verification_token: ActiveValue::Set(None),
+        verification_expires_at: ActiveValue::Set(None),
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)?;
+    let id = email.id;
+    match send_verification(&config, db, &user.username, email).await {
+        Ok(email) => Ok(ok(email)),
+        // no point keeping an address nobody can ever verify
+        Err(e) => {
+            Email::delete_by_id(id).exec(db).await.map_err(db_error)?;
+            Err(e)
+        }
+    }
+}
+
+#[post("/user/@me/emails/{email_id}/resend")]
+async fn resend(
+    path: web::Path<EmailId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let email = find(db, user.id, path.email_id).await?;
+    if email.verified_at.is_some() {
+        return Ok(ok(email));
+    }
+    Ok(ok(
+        send_verification(&config, db, &user.username, email).await?
+    ))
+}
+
+#[delete("/user/@me/emails/{email_id}")]
+async fn remove(
+    path: web::Path<EmailId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = Email::delete_many()
+        .filter(EmailCol::Id.eq(path.email_id))
+        .filter(EmailCol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+#[post("/user/@me/emails/{email_id}/verify")]
+async fn verify(
+    path: web::Path<EmailId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<CodeRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let email = find(db, user.id, path.email_id).await?;
+    if email.verified_at.is_some() {
+        return Ok(ok(email));
+    }
+    Ok(ok(verify_code(db, email.id, &req_data.code).await?))
+}
+
+/// Where the link from the verification mail goes, so no login needed. Only asks to confirm,
+/// mail scanners and link previews open links too and shouldn't verify anything on their own.
+#[get("/email/verify")]
+async fn verify_link(query: web::Query<VerifyQuery>) -> actix_web::Result<HttpResponse> {
+    // it ends up in the page, codes never have anything but letters and digits
+    if !query.token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(not_found("That code isn't valid (anymore)."));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!doctype html>\n<title>stoka</title>\n\
+             <form method=\"post\" action=\"verify\">\n\
+             <input type=\"hidden\" name=\"email_id\" value=\"{}\">\n\
+             <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+             <p>Send books from stoka to this address?</p>\n\
+             <button>Yes, verify it</button>\n\
+             </form>\n",
+            query.email_id, query.token
+        )))
+}
+
+/// What the page from [`verify_link`] sends.
+#[post("/email/verify")]
+async fn verify_form(
+    form: web::Form<VerifyQuery>,
+    db: web::Data<DatabaseConnection>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let email = verify_code(&db, form.email_id, &form.token).await?;
+    Ok(HttpResponse::Ok().body(format!(
+        "{} is verified, books can be sent there now.",
+        email.email
+    )))
+}
+
+#[post("/book/{book_id}/send")]
+async fn send(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: Option<web::Json<SendRequest>>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let req_data = req_data.map(|r| r.into_inner()).unwrap_or_default();
+    let mut select = Email::find()
+        .filter(EmailCol::UserId.eq(user.id))
+        .filter(EmailCol::VerifiedAt.is_not_null());
+    if let Some(id) = req_data.email_id {
+        select = select.filter(EmailCol::Id.eq(id));
+    }
+    let emails = select.all(db).await.map_err(db_error)?;
+    if emails.is_empty() {
+        return Err(bad_request("No verified address to send to."));
+    }
+
+    let data = storage::read(&config, &format!("{}.bin", book.hash))?;
+    let name = book.download_name();
+    let title = book
+        .meta
+        .as_ref()
+        .map_or_else(|| book.title.clone(), |m| m.title.clone());
+    let mut sent = vec![];
+    for email in emails {
+        mail::send(
+            &config,
+            &email.email,
+            &title,
+            format!("{} from your stoka library.\n", title),
+            Some(mail::File {
+                name: name.clone(),
+                mime: book.file_type.mime.clone(),
+                data: data.clone(),
+            }),
+        )
+        .await
+        .map_err(mail_error)?;
+        sent.push(email.email);
+    }
+    Ok(ok(sent))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(add)
+        .service(resend)
+        .service(verify)
+        .service(remove)
+        .service(send);
+}
+
+pub fn configure_na(cfg: &mut web::ServiceConfig) {
+    cfg.service(verify_link).service(verify_form);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+    use serde_json::{json, Value};
+
+    fn add_address(address: &str) -> actix_http::Request {
+        TestRequest::put()
+            .uri("/api/user/@me/emails")
+            .set_json(json!({ "email": address }))
+            .to_request()
+    }
+
+    fn verify_with(email_id: i32, code: &str) -> actix_http::Request {
+        TestRequest::post()
+            .uri(&format!("/api/user/@me/emails/{email_id}/verify"))
+            .set_json(json!({ "code": code }))
+            .to_request()
+    }
+
+    async fn stored(db: &DatabaseConnection, email_id: i32) -> EmailModel {
+        Email::find_by_id(email_id).one(db).await.unwrap().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn mailed_code_verifies_once() {
+        let (mut config, _dir) = testing::config();
+        let outbox = testing::smtp(&mut config);
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+
+        let res: Value = call_and_read_body_json(&app, add_address("once@example.com")).await;
+        let id = res["data"]["id"].as_i64().unwrap() as i32;
+        assert!(res["data"]["verified_at"].is_null());
+        let code = stored(&db, id).await.verification_token.unwrap();
+        let mails = outbox.lock().unwrap().clone();
+        assert_eq!(mails.len(), 1);
+        assert!(mails[0].contains("To: once@example.com"));
+        assert!(mails[0].contains(&code));
+
+        let res = call_service(&app, verify_with(id, "WRONG234")).await;
+        assert_eq!(res.status(), 404);
+        // typed in by hand, so case and stray spaces don't matter
+        let res: Value =
+            call_and_read_body_json(&app, verify_with(id, &format!(" {} ", code.to_lowercase())))
+                .await;
+        let verified_at = res["data"]["verified_at"].clone();
+        assert!(verified_at.is_i64());
+
+        // verifying again changes nothing, but the code itself is used up
+        let res: Value = call_and_read_body_json(&app, verify_with(id, &code)).await;
+        assert_eq!(res["data"]["verified_at"], verified_at);
+        assert!(verify_code(&db, id, &code).await.is_err());
+        assert!(stored(&db, id).await.verification_token.is_none());
+    }
+
+    #[actix_web::test]
+    async fn expired_codes_verify_nothing() {
+        let (mut config, _dir) = testing::config();
+        testing::smtp(&mut config);
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+
+        let res: Value = call_and_read_body_json(&app, add_address("late@example.com")).await;
+        let id = res["data"]["id"].as_i64().unwrap() as i32;
+        let email = stored(&db, id).await;
+        let code = email.verification_token.clone().unwrap();
+        let mut email: EmailActiveModel = email.into();
+        email.verification_expires_at = ActiveValue::Set(Some(Utc::now().timestamp() - 1));
+        email.update(&db).await.unwrap();
+
+        let res = call_service(&app, verify_with(id, &code)).await;
+        assert_eq!(res.status(), 404);
+        assert!(stored(&db, id).await.verified_at.is_none());
+    }
+
+    #[actix_web::test]
+    async fn codes_are_not_resent_right_away() {
+        let (mut config, _dir) = testing::config();
+        let outbox = testing::smtp(&mut config);
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+
+        let res: Value = call_and_read_body_json(&app, add_address("busy@example.com")).await;
+        let id = res["data"]["id"].as_i64().unwrap();
+        let again = TestRequest::post()
+            .uri(&format!("/api/user/@me/emails/{id}/resend"))
+            .to_request();
+        assert_eq!(call_service(&app, again).await.status(), 429);
+
+        // nor by removing the address and adding it again
+        let gone = TestRequest::delete()
+            .uri(&format!("/api/user/@me/emails/{id}"))
+            .to_request();
+        assert_eq!(call_service(&app, gone).await.status(), 200);
+        let res = call_service(&app, add_address("Busy@example.com")).await;
+        assert_eq!(res.status(), 429);
+        let left = testing::get_json(&app, "/api/user/@me/emails").await;
+        assert_eq!(left["data"], json!([]));
+        assert_eq!(outbox.lock().unwrap().len(), 1);
+
+        let now = Utc::now().timestamp();
+        assert!(claim_send("later@example.com", now).is_ok());
+        assert!(claim_send("later@example.com", now + RESEND_AFTER - 1).is_err());
+        assert!(claim_send("later@example.com", now + RESEND_AFTER).is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+//! The protocol KOReader's stock "Progress sync" plugin speaks, on top of stoka's own users
+//! and books. Point the plugin's custom sync server at `http(s)://<host>/kosync`.
+
+use super::progress::{self, Progress};
+use actix_web::http::StatusCode;
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, ResponseError};
+use argon2::verify_encoded;
+use entity::book::Column as BookCol;
+use entity::book::Model as BookModel;
+use entity::prelude::{Book, User};
+use entity::user::Column as UserCol;
+use entity::user::Model as UserModel;
+use md5::{Digest, Md5};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Mutex, PoisonError};
+
+/// Errors the way kosync reports them, the plugin shows `message` to the user.
+#[derive(Debug, Serialize)]
+struct KosyncError {
+    #[serde(skip)]
+    status: StatusCode,
+    code: u32,
+    message: String,
+}
+
+impl Display for KosyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl ResponseError for KosyncError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self)
+    }
+}
+
+fn server_error(e: impl ToString) -> KosyncError {
+    KosyncError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        code: 1000,
+        message: e.to_string(),
+    }
+}
+
+fn unauthorized() -> KosyncError {
+    KosyncError {
+        status: StatusCode::UNAUTHORIZED,
+        code: 2001,
+        message: "Unauthorized".to_string(),
+    }
+}
+
+fn invalid_request(message: &str) -> KosyncError {
+    KosyncError {
+        status: StatusCode::BAD_REQUEST,
+        code: 2003,
+        message: message.to_string(),
+    }
+}
+
+pub fn md5_hex(data: &[u8]) -> String {
+    hex::encode(Md5::digest(data))
+}
+
+/// KOReader's default way of telling documents apart: md5 over 1KiB samples taken at
+/// exponentially further offsets, so big files don't have to be read whole.
+pub fn partial_md5(data: &[u8]) -> String {
+    let mut hasher = Md5::new();
+    // the lua original starts at `lshift(1024, -2)`, which wraps around to 0
+    for offset in std::iter::once(0).chain((0..=10).map(|i| 1024usize << (2 * i))) {
+        if offset >= data.len() {
+            break;
+        }
+        hasher.update(&data[offset..data.len().min(offset + 1024)]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// A sync key that checked out, with the hash it was checked against.
+struct Verified {
+    hash: String,
+    /// sha256 of the key, so the key itself isn't kept around
+    key: [u8; 32],
+}
+
+/// KOReader sends its key along with every request and argon2 is slow on purpose, so a key
+/// only goes through it the first time. Setting a new sync password changes the hash, which
+/// is enough to make the old entry useless.
+static VERIFIED: Mutex<BTreeMap<i32, Verified>> = Mutex::new(BTreeMap::new());
+
+/// Checks the `x-auth-user`/`x-auth-key` headers every request but registration carries.
+async fn authorize(req: &HttpRequest, db: &DatabaseConnection) -> Result<UserModel, KosyncError> {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+    };
+    let (Some(username), Some(key)) = (header("x-auth-user"), header("x-auth-key")) else {
+        return Err(unauthorized());
+    };
+    let user = User::find()
+        .filter(UserCol::Username.eq(username))
+        .one(db)
+        .await
+        .map_err(server_error)?;
+    let Some((user, hash)) = user.and_then(|u| u.kosync_key.clone().map(|hash| (u, hash))) else {
+        return Err(unauthorized());
+    };
+    let key = key.to_lowercase();
+    let fingerprint: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+    let known = VERIFIED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&user.id)
+        .is_some_and(|v| v.hash == hash && v.key == fingerprint);
+    if !known {
+        let checked = hash.clone();
+        let valid = web::block(move || verify_encoded(&checked, key.as_bytes()).unwrap_or(false))
+            .await
+            .map_err(server_error)?;
+        if !valid {
+            return Err(unauthorized());
+        }
+        VERIFIED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                user.id,
+                Verified {
+                    hash,
+                    key: fingerprint,
+                },
+            );
+    }
+    Ok(user)
+}
+
+/// The user's book a kosync document hash stands for, the oldest one if they uploaded it twice.
+async fn document(
+    db: &DatabaseConnection,
+    user_id: i32,
+    document: &str,
+) -> Result<Option<BookModel>, KosyncError> {
+    Book::find()
+        .filter(BookCol::UserId.eq(user_id))
+        .filter(BookCol::PartialMd5.eq(document.to_lowercase()))
+        .order_by_asc(BookCol::Id)
+        .one(db)
+        .await
+        .map_err(server_error)
+}
+
+#[derive(Deserialize)]
+struct DocumentPath {
+    document: String,
+}
+
+#[derive(Deserialize)]
+struct ProgressRequest {
+    document: String,
+    /// xpointer for reflowable documents, page number for the rest
+    progress: String,
+    percentage: f64,
+    device: String,
+    device_id: String,
+}
+
+#[derive(Serialize)]
+struct ProgressResponse {
+    document: String,
+    percentage: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_id: Option<String>,
+    timestamp: i64,
+}
+
+/// Accounts are made in stoka, registering from KOReader only tells you how.
+#[post("/users/create")]
+async fn create() -> Result<HttpResponse, KosyncError> {
+    Err(KosyncError {
+        status: StatusCode::FORBIDDEN,
+        code: 2005,
+        message: "Sign up with stoka and set a sync password under /api/user/@me/kosync."
+            .to_string(),
+    })
+}
+
+#[get("/users/auth")]
+async fn auth(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+) -> Result<HttpResponse, KosyncError> {
+    authorize(&req, &db).await?;
+    Ok(HttpResponse::Ok().json(json!({ "authorized": "OK" })))
+}
+
+#[put("/syncs/progress")]
+async fn update_progress(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    req_data: web::Json<ProgressRequest>,
+) -> Result<HttpResponse, KosyncError> {
+    let db: &DatabaseConnection = &db;
+    let user = authorize(&req, db).await?;
+    let req_data = req_data.into_inner();
+    if !(0.0..=1.0).contains(&req_data.percentage) {
+        return Err(invalid_request("percentage has to be between 0 and 1."));
+    }
+    let Some(book) = document(db, user.id, &req_data.document).await? else {
+        return Err(KosyncError {
+            status: StatusCode::NOT_FOUND,
+            code: 2003,
+            message: "This document isn't in your stoka library.".to_string(),
+        });
+    };
+    let stored = progress::store(
+        db,
+        user.id,
+        book.id,
+        Progress {
+            percentage: req_data.percentage,
+            position: Some(req_data.progress),
+            device: Some(req_data.device),
+            device_id: Some(req_data.device_id),
+            timestamp: None,
+        },
+    )
+    .await
+    .map_err(server_error)?;
+    // a later position from elsewhere wins, the device picks it up on its next pull
+    let timestamp = match stored {
+        Ok(p) | Err(p) => p.updated_at,
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "document": req_data.document,
+        "timestamp": timestamp,
+    })))
+}
+
+#[get("/syncs/progress/{document}")]
+async fn get_progress(
+    req: HttpRequest,
+    path: web::Path<DocumentPath>,
+    db: web::Data<DatabaseConnection>,
+) -> Result<HttpResponse, KosyncError> {
+    let db: &DatabaseConnection = &db;
+    let user = authorize(&req, db).await?;
+    let progress = match document(db, user.id, &path.document).await? {
+        Some(book) => progress::find(db, user.id, book.id)
+            .await
+            .map_err(server_error)?,
+        None => None,
+    };
+    // kosync answers documents it knows nothing about with an empty object
+    Ok(match progress {
+        Some(p) => HttpResponse::Ok().json(ProgressResponse {
+            document: path.into_inner().document,
+            percentage: p.percentage,
+            progress: p.position,
+            device: p.device,
+            device_id: p.device_id,
+            timestamp: p.updated_at,
+        }),
+        None => HttpResponse::Ok().json(json!({})),
+    })
+}
+
+#[get("/healthcheck")]
+async fn healthcheck() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "state": "OK" }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create)
+        .service(auth)
+        .service(update_progress)
+        .service(get_progress)
+        .service(healthcheck);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::user::hash_password;
+    use crate::testing;
+    use actix_web::test::TestRequest;
+    use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
+
+    fn request(username: &str, key: &str) -> HttpRequest {
+        TestRequest::default()
+            .insert_header(("x-auth-user", username))
+            .insert_header(("x-auth-key", key))
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn sync_keys_are_remembered_until_the_password_changes() {
+        let db = testing::db().await;
+        let user = testing::user(&db, "reader").await;
+        let set_password = |password: &str| {
+            let mut user = user.clone().into_active_model();
+            user.kosync_key = ActiveValue::Set(Some(hash_password(
+                md5_hex(password.as_bytes()),
+                "0123456789abcdef".to_string(),
+            )));
+            user.update(&db)
+        };
+        set_password("first").await.unwrap();
+        let key = md5_hex(b"first").to_uppercase();
+
+        assert!(authorize(&request("reader", &md5_hex(b"wrong")), &db)
+            .await
+            .is_err());
+        assert!(authorize(&request("nobody", &key), &db).await.is_err());
+        assert!(!VERIFIED.lock().unwrap().contains_key(&user.id));
+        assert_eq!(
+            authorize(&request("reader", &key), &db).await.unwrap().id,
+            user.id
+        );
+        assert!(VERIFIED.lock().unwrap().contains_key(&user.id));
+        assert!(authorize(&request("reader", &key), &db).await.is_ok());
+        assert!(authorize(&request("reader", &md5_hex(b"wrong")), &db)
+            .await
+            .is_err());
+
+        set_password("second").await.unwrap();
+        assert!(authorize(&request("reader", &key), &db).await.is_err());
+        assert!(authorize(&request("reader", &md5_hex(b"second")), &db)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn partial_md5_matches_koreader() {
+        // expected value from a line by line port of KOReader's `util.partialMD5`, a 3 MB
+        // file gets sampled 1 KiB at 0, 1K, 4K, 16K, 64K, 256K and 1M
+        let data: Vec<u8> = (0..3_000_000u32)
+            .map(|i| (i * 7 + i / 1024) as u8)
+            .collect();
+        assert_eq!(partial_md5(&data), "b861e18eedb959dfeffb075cafaa1683");
+    }
+
+    #[test]
+    fn small_files_are_hashed_whole() {
+        assert_eq!(partial_md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        let data: Vec<u8> = (0..2048u32).map(|i| i as u8).collect();
+        assert_eq!(partial_md5(&data), md5_hex(&data));
+    }
+}
@@ -0,0 +1,387 @@
+use super::book::{serve_book, BookId};
+use super::user::hash_password;
+use super::{bad_request, db_error, not_found, ok};
+use crate::auth::LinkClaims;
+use crate::config::Config;
+use crate::{AuthData, ErrorResponse};
+use actix_web::{delete, error, get, post, put, web, HttpRequest, HttpResponse};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use argon2::verify_encoded;
+use chrono::Utc;
+use entity::prelude::ShareLink;
+use entity::share_link::ActiveModel as SLActiveModel;
+use entity::share_link::Column as SLCol;
+use entity::share_link::Model as SLModel;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+
+/// hours
+const DEFAULT_VALID_FOR: i64 = 24 * 7;
+const MAX_VALID_FOR: i64 = 24 * 365;
+
+#[derive(Deserialize)]
+struct LinkId {
+    link_id: i32,
+}
+
+#[derive(Deserialize)]
+struct TokenPath {
+    token: String,
+}
+
+#[derive(Deserialize, Default)]
+struct LinkRequest {
+    /// hours until the link stops working, a week if left out
+    valid_for: Option<i64>,
+    password: Option<String>,
+    max_downloads: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct LinkQuery {
+    book_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct PasswordRequest {
+    password: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LinkInfo {
+    #[serde(flatten)]
+    link: SLModel,
+    password_protected: bool,
+    token: String,
+    /// `None` unless the config has a `public_url` to build it from
+    url: Option<String>,
+}
+
+/// What someone with the link gets to see before downloading.
+#[derive(Serialize)]
+struct PublicInfo {
+    title: String,
+    creator: Option<String>,
+    file_type: String,
+    size: Option<i64>,
+    password_protected: bool,
+    expires_at: i64,
+    /// `None` if there's no limit
+    downloads_left: Option<i32>,
+}
+
+fn gone(e: &str) -> actix_web::Error {
+    error::ErrorGone(ErrorResponse {
+        status: "error".to_string(),
+        error: e.to_string(),
+    })
+}
+
+fn link_info(config: &Config, link: SLModel) -> actix_web::Result<LinkInfo> {
+    let token = LinkClaims {
+        link_id: link.id,
+        exp: link.expires_at,
+    }
+    .create_token(&config.jwt.private_key)?;
+    // the Host header is up to whoever asked, so only ever link to the configured address
+    let url = config
+        .public_url
+        .as_ref()
+        .map(|url| format!("{}/na/link/{}", url.trim_end_matches('/'), token));
+    Ok(LinkInfo {
+        url,
+        token,
+        password_protected: link.password.is_some(),
+        link,
+    })
+}
+
+/// Links that still work, the ones that expired or ran out of downloads just stay in the table.
+fn active(now: i64) -> Condition {
+    Condition::all().add(SLCol::ExpiresAt.gt(now)).add(
+        Condition::any()
+            .add(SLCol::MaxDownloads.is_null())
+            .add(Expr::col(SLCol::Downloads).lt(Expr::col(SLCol::MaxDownloads))),
+    )
+}
+
+/// The link a token stands for, as long as it's still good for a download.
+async fn open(db: &DatabaseConnection, config: &Config, token: &str) -> actix_web::Result<SLModel> {
+    let missing = || not_found("This link doesn't exist (anymore).".to_string());
+    let claims = LinkClaims::from_token(token, &config.jwt.public_key).map_err(|_| missing())?;
+    let link = ShareLink::find_by_id(claims.link_id)
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(missing)?;
+    if link.expires_at <= Utc::now().timestamp() {
+        return Err(gone("This link expired."));
+    }
+    if link.max_downloads.is_some_and(|max| link.downloads >= max) {
+        return Err(gone("This link has been used up."));
+    }
+    Ok(link)
+}
+
+#[put("/book/{book_id}/links")]
+async fn create(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: Option<web::Json<LinkRequest>>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let req_data = req_data.map(|r| r.into_inner()).unwrap_or_default();
+    let valid_for = req_data.valid_for.unwrap_or(DEFAULT_VALID_FOR);
+    if !(1..=MAX_VALID_FOR).contains(&valid_for) {
+        return Err(bad_request(
+            "valid_for has to be between 1 hour and a year.",
+        ));
+    }
+    if req_data.max_downloads.is_some_and(|max| max < 1) {
+        return Err(bad_request("max_downloads has to be at least 1."));
+    }
+    let password = match req_data.password.filter(|p| !p.is_empty()) {
+        Some(password) => {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            Some(web::block(move || hash_password(password, hex::encode(salt))).await?)
+        }
+        None => None,
+    };
+    let now = Utc::now().timestamp();
+    let link = SLActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user.id),
+        book_id: ActiveValue::Set(book.id),
+        password: ActiveValue::Set(password),
+        max_downloads: ActiveValue::Set(req_data.max_downloads),
+        downloads: ActiveValue::Set(0),
+        expires_at: ActiveValue::Set(now + valid_for * 60 * 60),
+        created_at: ActiveValue::Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(db_error)?;
+    Ok(ok(link_info(&config, link)?))
+}
+
+/// The user's links that still work, newest first.
+#[get("/links")]
+async fn list(
+    query: web::Query<LinkQuery>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let mut select = ShareLink::find()
+        .filter(SLCol::UserId.eq(user.id))
+        .filter(active(Utc::now().timestamp()));
+    if let Some(book_id) = query.book_id {
+        select = select.filter(SLCol::BookId.eq(book_id));
+    }
+    let links = select
+        .order_by_desc(SLCol::CreatedAt)
+        .order_by_desc(SLCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|link| link_info(&config, link))
+        .collect::<actix_web::Result<Vec<_>>>()?;
+    Ok(ok(links))
+}
+
+#[delete("/links/{link_id}")]
+async fn revoke(
+    path: web::Path<LinkId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = ShareLink::delete_many()
+        .filter(SLCol::Id.eq(path.link_id))
+        .filter(SLCol::UserId.eq(user.id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+#[get("/link/{token}")]
+async fn public_info(
+    path: web::Path<TokenPath>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let link = open(db, &config, &path.token).await?;
+    let book = (BookId {
+        book_id: link.book_id,
+    })
+    .get(link.user_id, db)
+    .await
+    .map_err(not_found)?;
+    Ok(ok(PublicInfo {
+        title: book
+            .meta
+            .as_ref()
+            .map_or_else(|| book.title.clone(), |m| m.title.clone()),
+        creator: book.meta.as_ref().map(|m| m.creator.clone()),
+        file_type: book.file_type.name,
+        size: book.size,
+        password_protected: link.password.is_some(),
+        expires_at: link.expires_at,
+        downloads_left: link.max_downloads.map(|max| max - link.downloads),
+    }))
+}
+
+/// Counts the download and sends the file. Links with a password need a POST with it, so it
+/// doesn't end up in anyone's logs.
+async fn public_download(
+    req: &HttpRequest,
+    config: &Config,
+    db: &DatabaseConnection,
+    token: &str,
+    password: Option<String>,
+) -> actix_web::Result<HttpResponse> {
+    let link = open(db, config, token).await?;
+    if let Some(hash) = &link.password {
+        let Some(password) = password else {
+            return Err(error::ErrorUnauthorized(ErrorResponse {
+                status: "error".to_string(),
+                error: "This link needs a password.".to_string(),
+            }));
+        };
+        // argon2 is slow on purpose, keep it off the executor
+        let hash = hash.clone();
+        let valid =
+            web::block(move || verify_encoded(&hash, password.as_bytes()).unwrap_or(false)).await?;
+        if !valid {
+            return Err(error::ErrorUnauthorized(ErrorResponse {
+                status: "error".to_string(),
+                error: "Wrong password.".to_string(),
+            }));
+        }
+    }
+    // checked and counted in one go, so two downloads at once can't both take the last one
+    let counted = ShareLink::update_many()
+        .col_expr(SLCol::Downloads, Expr::col(SLCol::Downloads).add(1))
+        .filter(SLCol::Id.eq(link.id))
+        .filter(active(Utc::now().timestamp()))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    if counted.rows_affected == 0 {
+        return Err(gone("This link has been used up."));
+    }
+    let book = (BookId {
+        book_id: link.book_id,
+    })
+    .get(link.user_id, db)
+    .await
+    .map_err(not_found)?;
+    serve_book(req, config, book).await
+}
+
+#[get("/link/{token}/dl")]
+async fn download(
+    req: HttpRequest,
+    path: web::Path<TokenPath>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+) -> actix_web::Result<HttpResponse> {
+    public_download(&req, &config, &db, &path.token, None).await
+}
+
+#[post("/link/{token}/dl")]
+async fn download_with_password(
+    req: HttpRequest,
+    path: web::Path<TokenPath>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    req_data: Option<web::Json<PasswordRequest>>,
+) -> actix_web::Result<HttpResponse> {
+    let password = req_data.and_then(|r| r.into_inner().password);
+    public_download(&req, &config, &db, &path.token, password).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create).service(list).service(revoke);
+}
+
+pub fn configure_na(cfg: &mut web::ServiceConfig) {
+    cfg.service(public_info)
+        .service(download)
+        .service(download_with_password);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+    use serde_json::{json, Value};
+
+    /// Both halves in one app, the public ones end up under `/api` too.
+    fn routes(cfg: &mut web::ServiceConfig) {
+        configure(cfg);
+        configure_na(cfg);
+    }
+
+    #[actix_web::test]
+    async fn links_point_at_the_public_url_whatever_the_host() {
+        let (mut config, _dir) = testing::config();
+        config.public_url = Some("https://books.example.com/".to_string());
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let pdf = b"%PDF-1.4\n%%EOF\n".to_vec();
+        let book = add_book(&config, &db, &search, user.id, Some("a.pdf".into()), pdf)
+            .await
+            .unwrap();
+        let app = testing::app(&config, &db, &search, &user, routes).await;
+
+        let req = TestRequest::put()
+            .uri(&format!("/api/book/{book}/links"))
+            .insert_header(("Host", "evil.example"))
+            .set_json(json!({ "password": "secret" }))
+            .to_request();
+        let res: Value = call_and_read_body_json(&app, req).await;
+        let token = res["data"]["token"].as_str().unwrap();
+        assert_eq!(
+            res["data"]["url"],
+            format!("https://books.example.com/na/link/{token}")
+        );
+        assert_eq!(res["data"]["password_protected"], true);
+
+        let fetch = |password: Option<&str>| {
+            TestRequest::post()
+                .uri(&format!("/api/link/{token}/dl"))
+                .set_json(json!({ "password": password }))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, fetch(None)).await.status(), 401);
+        assert_eq!(call_service(&app, fetch(Some("guess"))).await.status(), 401);
+        assert_eq!(
+            call_service(&app, fetch(Some("secret"))).await.status(),
+            200
+        );
+
+        // without a public url there's nothing trustworthy to build one from
+        config.public_url = None;
+        let app = testing::app(&config, &db, &search, &user, routes).await;
+        let links = testing::get_json(&app, "/api/links").await;
+        assert_eq!(links["data"][0]["token"], token);
+        assert!(links["data"][0]["url"].is_null());
+    }
+}
@@ -0,0 +1,189 @@
+use super::book::BookId;
+use super::{bad_request, db_error, not_found, ok};
+use crate::{AuthData, ErrorResponse};
+use actix_web::{error, get, put, web};
+use chrono::Utc;
+use entity::prelude::ReadingProgress;
+use entity::reading_progress::ActiveModel as RPActiveModel;
+use entity::reading_progress::Column as RPCol;
+use entity::reading_progress::Model as RPModel;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+use serde::Deserialize;
+
+/// Where a device got to in a book and when.
+#[derive(Deserialize)]
+pub(crate) struct Progress {
+    /// 0.0 to 1.0
+    pub(crate) percentage: f64,
+    pub(crate) position: Option<String>,
+    pub(crate) device: Option<String>,
+    pub(crate) device_id: Option<String>,
+    /// when the device got there, now if left out
+    pub(crate) timestamp: Option<i64>,
+}
+
+pub(crate) async fn find(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    book_id: i32,
+) -> Result<Option<RPModel>, DbErr> {
+    ReadingProgress::find()
+        .filter(RPCol::UserId.eq(user_id))
+        .filter(RPCol::BookId.eq(book_id))
+        .one(db)
+        .await
+}
+
+/// Stores where a user is in a book unless some device already reported a later position,
+/// in which case that one is handed back in `Err`.
+pub(crate) async fn store(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+    new: Progress,
+) -> Result<Result<RPModel, RPModel>, DbErr> {
+    let now = Utc::now().timestamp();
+    // a device with its clock running ahead would win against everything else for good
+    let timestamp = new.timestamp.map_or(now, |t| t.min(now));
+    // the row stays locked until we're done so two devices syncing at once can't both win
+    let txn = db.begin().await?;
+    let current = ReadingProgress::find()
+        .filter(RPCol::UserId.eq(user_id))
+        .filter(RPCol::BookId.eq(book_id))
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    let progress = RPActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        book_id: ActiveValue::Set(book_id),
+        percentage: ActiveValue::Set(new.percentage),
+        position: ActiveValue::Set(new.position),
+        device: ActiveValue::Set(new.device),
+        device_id: ActiveValue::Set(new.device_id),
+        updated_at: ActiveValue::Set(timestamp),
+    };
+    let stored = match current {
+        // last writer wins, by when the reading happened rather than when it got to us
+        Some(current) if current.updated_at > timestamp => Err(current),
+        Some(current) => Ok(RPActiveModel {
+            id: ActiveValue::Unchanged(current.id),
+            ..progress
+        }
+        .update(&txn)
+        .await?),
+        None => Ok(progress.insert(&txn).await?),
+    };
+    txn.commit().await?;
+    Ok(stored)
+}
+
+#[get("/book/{book_id}/progress")]
+async fn get(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    match find(db, user.id, book.id).await.map_err(db_error)? {
+        Some(progress) => Ok(ok(progress)),
+        None => Err(not_found("This book hasn't been opened yet.".to_string())),
+    }
+}
+
+#[put("/book/{book_id}/progress")]
+async fn set(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<Progress>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    if !(0.0..=1.0).contains(&req_data.percentage) {
+        return Err(bad_request("percentage has to be between 0 and 1."));
+    }
+    match store(db, user.id, book.id, req_data.into_inner())
+        .await
+        .map_err(db_error)?
+    {
+        Ok(progress) => Ok(ok(progress)),
+        Err(newer) => Err(error::ErrorConflict(ErrorResponse {
+            status: "error".to_string(),
+            error: format!(
+                "{} reported a later position at {}.",
+                newer.device.as_deref().unwrap_or("Another device"),
+                newer.updated_at
+            ),
+        })),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get).service(set);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+
+    fn at(percentage: f64, device: &str, timestamp: i64) -> Progress {
+        Progress {
+            percentage,
+            position: None,
+            device: Some(device.to_string()),
+            device_id: None,
+            timestamp: Some(timestamp),
+        }
+    }
+
+    #[actix_web::test]
+    async fn later_reading_wins() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let pdf = b"%PDF-1.4\n%%EOF\n".to_vec();
+        let book = add_book(&config, &db, &search, user.id, Some("a.pdf".into()), pdf)
+            .await
+            .unwrap();
+
+        let stored = store(&db, user.id, book, at(0.5, "kobo", 2000))
+            .await
+            .unwrap();
+        assert_eq!(stored.unwrap().percentage, 0.5);
+
+        // the phone synced late, what it read happened before the kobo got to the middle
+        let stale = store(&db, user.id, book, at(0.2, "phone", 1000))
+            .await
+            .unwrap();
+        let newer = stale.unwrap_err();
+        assert_eq!((newer.percentage, newer.updated_at), (0.5, 2000));
+        let current = find(&db, user.id, book).await.unwrap().unwrap();
+        assert_eq!(current.device.as_deref(), Some("kobo"));
+
+        let stored = store(&db, user.id, book, at(0.7, "phone", 3000))
+            .await
+            .unwrap();
+        assert_eq!(stored.unwrap().device.as_deref(), Some("phone"));
+
+        // a clock in the future doesn't get to lock everyone else out
+        let ahead = store(&db, user.id, book, at(0.8, "kobo", i64::MAX))
+            .await
+            .unwrap();
+        assert!(ahead.unwrap().updated_at <= Utc::now().timestamp());
+        let stored = store(
+            &db,
+            user.id,
+            book,
+            at(0.9, "phone", Utc::now().timestamp() + 1),
+        );
+        assert!(stored.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,494 @@
+use super::book::BookId;
+use super::{bad_request, db_error, not_found, ok};
+use crate::AuthData;
+use actix_web::{get, post, put, web};
+use chrono::{NaiveDateTime, Utc};
+use entity::book::Column as BookCol;
+use entity::prelude::{Book, ReadingSession, ReadingStatus};
+use entity::reading_session::ActiveModel as RSessActiveModel;
+use entity::reading_session::Column as RSessCol;
+use entity::reading_session::Model as RSessModel;
+use entity::reading_status::ActiveModel as RStatActiveModel;
+use entity::reading_status::Column as RStatCol;
+use entity::reading_status::Model as RStatModel;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TryIntoModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Unread,
+    Reading,
+    Finished,
+    Abandoned,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Unread => "unread",
+            Status::Reading => "reading",
+            Status::Finished => "finished",
+            Status::Abandoned => "abandoned",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusRequest {
+    status: Status,
+    /// when it happened, now if left out
+    timestamp: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct BookStatus {
+    book_id: i32,
+    status: String,
+    started_at: Option<i64>,
+    finished_at: Option<i64>,
+    /// `None` for books nobody set a status on yet
+    updated_at: Option<i64>,
+}
+
+impl From<RStatModel> for BookStatus {
+    fn from(s: RStatModel) -> Self {
+        BookStatus {
+            book_id: s.book_id,
+            status: s.status,
+            started_at: s.started_at,
+            finished_at: s.finished_at,
+            updated_at: Some(s.updated_at),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionRequest {
+    /// unix timestamps
+    started_at: i64,
+    ended_at: i64,
+    pages: Option<i32>,
+    device: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    /// minutes east of UTC, decides where days and months start
+    #[serde(default)]
+    utc_offset: i64,
+}
+
+#[derive(Serialize)]
+struct MonthCount {
+    /// `YYYY-MM`
+    month: String,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    /// how many books are in each status
+    books: BTreeMap<&'static str, u64>,
+    finished_per_month: Vec<MonthCount>,
+    /// seconds
+    time_read: i64,
+    pages_read: i64,
+    sessions: u64,
+    /// days in a row with at least one session, still counts if today's hasn't happened yet
+    current_streak: u32,
+    longest_streak: u32,
+}
+
+async fn find_status(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+) -> Result<Option<RStatModel>, DbErr> {
+    ReadingStatus::find()
+        .filter(RStatCol::UserId.eq(user_id))
+        .filter(RStatCol::BookId.eq(book_id))
+        .one(db)
+        .await
+}
+
+/// Moves a book to a new status, keeping track of when reading it started and finished.
+async fn set_status(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+    status: Status,
+    at: i64,
+) -> Result<RStatModel, DbErr> {
+    let current = find_status(db, user_id, book_id).await?;
+    let started = current.as_ref().and_then(|s| s.started_at);
+    let (started_at, finished_at) = match status {
+        Status::Unread => (None, None),
+        Status::Reading => (started.or(Some(at)), None),
+        Status::Finished => (started.or(Some(at)), Some(at)),
+        Status::Abandoned => (started, None),
+    };
+    let mut model: RStatActiveModel = match current {
+        Some(current) => current.into(),
+        None => RStatActiveModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(user_id),
+            book_id: ActiveValue::Set(book_id),
+            ..Default::default()
+        },
+    };
+    model.status = ActiveValue::Set(status.as_str().to_string());
+    model.started_at = ActiveValue::Set(started_at);
+    model.finished_at = ActiveValue::Set(finished_at);
+    model.updated_at = ActiveValue::Set(Utc::now().timestamp());
+    model.save(db).await?.try_into_model()
+}
+
+async fn book_sessions(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+) -> Result<Vec<RSessModel>, DbErr> {
+    ReadingSession::find()
+        .filter(RSessCol::UserId.eq(user_id))
+        .filter(RSessCol::BookId.eq(book_id))
+        .order_by_asc(RSessCol::StartedAt)
+        .all(db)
+        .await
+}
+
+/// Whether `timestamp` could be a time somebody read at: not before 1970 and not more than a day
+/// ahead, which leaves room for a device with a badly set clock.
+fn plausible(timestamp: i64) -> bool {
+    (0..=Utc::now().timestamp() + DAY).contains(&timestamp)
+}
+
+/// Day number since the epoch that `timestamp` falls on, `offset` seconds east of UTC.
+fn day(timestamp: i64, offset: i64) -> i64 {
+    (timestamp + offset).div_euclid(DAY)
+}
+
+/// Current and longest run of consecutive days, `days` counted from the epoch.
+fn streaks(days: &BTreeSet<i64>, today: i64) -> (u32, u32) {
+    let (mut longest, mut run, mut last) = (0, 0, None);
+    for day in days {
+        run = match last {
+            Some(last) if last + 1 == *day => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        last = Some(*day);
+    }
+    let current = match last {
+        Some(last) if last >= today - 1 => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+#[get("/book/{book_id}/status")]
+async fn get_status(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let status = find_status(db, user.id, book.id).await.map_err(db_error)?;
+    Ok(ok(match status {
+        Some(status) => BookStatus::from(status),
+        None => BookStatus {
+            book_id: book.id,
+            status: Status::Unread.as_str().to_string(),
+            started_at: None,
+            finished_at: None,
+            updated_at: None,
+        },
+    }))
+}
+
+#[put("/book/{book_id}/status")]
+async fn put_status(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<StatusRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let at = req_data.timestamp.unwrap_or_else(|| Utc::now().timestamp());
+    if !plausible(at) {
+        return Err(bad_request(
+            "timestamp must be after 1970 and not in the future.",
+        ));
+    }
+    let status = set_status(db, user.id, book.id, req_data.status, at)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(BookStatus::from(status)))
+}
+
+#[get("/book/{book_id}/sessions")]
+async fn get_sessions(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    Ok(ok(book_sessions(db, user.id, book.id)
+        .await
+        .map_err(db_error)?))
+}
+
+/// Records sessions a device kept, ones we already have (same start) are skipped so devices
+/// can just send everything again.
+#[post("/book/{book_id}/sessions")]
+async fn add_sessions(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<Vec<SessionRequest>>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    if req_data
+        .iter()
+        .any(|s| !plausible(s.started_at) || !plausible(s.ended_at))
+    {
+        return Err(bad_request(
+            "Sessions must be after 1970 and not in the future.",
+        ));
+    }
+    if req_data
+        .iter()
+        .any(|s| s.ended_at < s.started_at || s.pages.is_some_and(|p| p < 0))
+    {
+        return Err(bad_request(
+            "Sessions can't end before they start or have negative pages.",
+        ));
+    }
+    let mut known: HashSet<i64> = book_sessions(db, user.id, book.id)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|s| s.started_at)
+        .collect();
+    let new: Vec<RSessActiveModel> = req_data
+        .into_inner()
+        .into_iter()
+        .filter(|s| known.insert(s.started_at))
+        .map(|s| RSessActiveModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(user.id),
+            book_id: ActiveValue::Set(book.id),
+            started_at: ActiveValue::Set(s.started_at),
+            ended_at: ActiveValue::Set(s.ended_at),
+            pages: ActiveValue::Set(s.pages),
+            device: ActiveValue::Set(s.device),
+        })
+        .collect();
+    // opening a book you haven't started means you are reading it now
+    let first = new.iter().filter_map(|s| s.started_at.clone().take()).min();
+    if !new.is_empty() {
+        ReadingSession::insert_many(new)
+            .exec(db)
+            .await
+            .map_err(db_error)?;
+    }
+    if let Some(first) = first {
+        let status = find_status(db, user.id, book.id).await.map_err(db_error)?;
+        if status.is_none_or(|s| s.status == Status::Unread.as_str()) {
+            set_status(db, user.id, book.id, Status::Reading, first)
+                .await
+                .map_err(db_error)?;
+        }
+    }
+    Ok(ok(book_sessions(db, user.id, book.id)
+        .await
+        .map_err(db_error)?))
+}
+
+#[get("/user/@me/stats")]
+async fn stats(
+    query: web::Query<StatsQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    // no time zone is further than 14 hours from UTC
+    if query.utc_offset.abs() > 14 * 60 {
+        return Err(bad_request("utc_offset must be within 14 hours of UTC."));
+    }
+    let offset = query.utc_offset * 60;
+
+    let total = Book::find()
+        .filter(BookCol::UserId.eq(user.id))
+        .count(db)
+        .await
+        .map_err(db_error)?;
+    let statuses = ReadingStatus::find()
+        .filter(RStatCol::UserId.eq(user.id))
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut books = BTreeMap::new();
+    for status in [Status::Reading, Status::Finished, Status::Abandoned] {
+        let count = statuses
+            .iter()
+            .filter(|s| s.status == status.as_str())
+            .count();
+        books.insert(status.as_str(), count as u64);
+    }
+    books.insert(
+        Status::Unread.as_str(),
+        total.saturating_sub(books.values().sum()),
+    );
+
+    let mut months: BTreeMap<String, u64> = BTreeMap::new();
+    for finished in statuses.iter().filter_map(|s| s.finished_at) {
+        if let Some(date) = NaiveDateTime::from_timestamp_opt(finished + offset, 0) {
+            *months.entry(date.format("%Y-%m").to_string()).or_default() += 1;
+        }
+    }
+
+    let sessions = ReadingSession::find()
+        .select_only()
+        .column(RSessCol::StartedAt)
+        .column(RSessCol::EndedAt)
+        .column(RSessCol::Pages)
+        .filter(RSessCol::UserId.eq(user.id))
+        .into_tuple::<(i64, i64, Option<i32>)>()
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let days: BTreeSet<i64> = sessions
+        .iter()
+        .map(|(start, _, _)| day(*start, offset))
+        .collect();
+    let today = day(Utc::now().timestamp(), offset);
+    let (current_streak, longest_streak) = streaks(&days, today);
+
+    Ok(ok(Stats {
+        books,
+        finished_per_month: months
+            .into_iter()
+            .map(|(month, count)| MonthCount { month, count })
+            .collect(),
+        time_read: sessions.iter().map(|(start, end, _)| end - start).sum(),
+        pages_read: sessions
+            .iter()
+            .filter_map(|(_, _, pages)| pages.map(i64::from))
+            .sum(),
+        sessions: sessions.len() as u64,
+        current_streak,
+        longest_streak,
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_status)
+        .service(put_status)
+        .service(get_sessions)
+        .service(add_sessions)
+        .service(stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use actix_web::test::{call_service, TestRequest};
+    use serde_json::json;
+
+    fn set(days: &[i64]) -> BTreeSet<i64> {
+        days.iter().copied().collect()
+    }
+
+    #[test]
+    fn no_days_no_streaks() {
+        assert_eq!(streaks(&set(&[]), 100), (0, 0));
+    }
+
+    #[test]
+    fn gaps_break_runs() {
+        assert_eq!(streaks(&set(&[1, 2, 3, 5, 6, 10]), 10), (1, 3));
+        assert_eq!(streaks(&set(&[1, 3, 5, 7]), 50), (0, 1));
+    }
+
+    #[test]
+    fn current_streak_counts_from_today_or_yesterday() {
+        assert_eq!(streaks(&set(&[7, 8, 9, 10]), 10), (4, 4));
+        assert_eq!(streaks(&set(&[7, 8, 9]), 10), (3, 3));
+        assert_eq!(streaks(&set(&[7, 8]), 10), (0, 2));
+    }
+
+    #[test]
+    fn offset_moves_the_day_boundary() {
+        // 2024-01-01 23:30 UTC is already the 2nd an hour east and still the 1st an hour west
+        let ts = 1_704_151_800;
+        assert_eq!(day(ts, 0), 19723);
+        assert_eq!(day(ts, 3600), 19724);
+        assert_eq!(day(ts, -3600), 19723);
+        // and just after midnight UTC it's still the previous day west of it
+        assert_eq!(day(1_704_153_660, -3600), 19723);
+        assert_eq!(day(1_704_153_660, 0), 19724);
+    }
+
+    #[test]
+    fn offset_decides_whether_sessions_make_a_streak() {
+        // 23:00 and 01:00 UTC are two days in a row in UTC but the same evening in UTC-2
+        let sessions = [1_704_150_000, 1_704_157_200];
+        let days = |offset| sessions.iter().map(|s| day(*s, offset)).collect();
+        assert_eq!(streaks(&days(0), 19724), (2, 2));
+        assert_eq!(streaks(&days(-2 * 3600), 19724), (1, 1));
+    }
+
+    #[actix_web::test]
+    async fn sessions_out_of_any_sane_range_are_refused() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let pdf = b"%PDF-1.4\n%%EOF\n".to_vec();
+        let book = add_book(&config, &db, &search, user.id, Some("a.pdf".into()), pdf)
+            .await
+            .unwrap();
+        let now = Utc::now().timestamp();
+        for (started_at, ended_at) in [
+            (i64::MIN, i64::MAX),
+            (i64::MIN, now),
+            (now, i64::MAX),
+            (-DAY, now),
+            (now, now + 2 * DAY),
+        ] {
+            let req = TestRequest::post()
+                .uri(&format!("/api/book/{book}/sessions"))
+                .set_json(json!([{ "started_at": started_at, "ended_at": ended_at }]))
+                .to_request();
+            assert_eq!(call_service(&app, req).await.status(), 400);
+        }
+        let req = TestRequest::put()
+            .uri(&format!("/api/book/{book}/status"))
+            .set_json(json!({ "status": "finished", "timestamp": i64::MAX }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 400);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/book/{book}/sessions"))
+            .set_json(json!([{ "started_at": now - 600, "ended_at": now, "pages": 12 }]))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), 200);
+        let summary = testing::get_json(&app, "/api/user/@me/stats").await;
+        assert_eq!(summary["data"]["time_read"], 600);
+        assert_eq!(summary["data"]["pages_read"], 12);
+    }
+}
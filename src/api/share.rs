@@ -0,0 +1,519 @@
+use super::book::{copy_book, full_books, serve_book, BookId, FullBook};
+use super::{bad_request, db_error, not_found, ok};
+use crate::config::Config;
+use crate::search::Search;
+use crate::{AuthData, ErrorResponse};
+use actix_web::{delete, error, get, post, put, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use entity::book::Column as BookCol;
+use entity::book::Model as BookModel;
+use entity::collection::Column as CCol;
+use entity::collection_book::Column as CBCol;
+use entity::prelude::{Book, Collection, CollectionBook, FileType, Share, User};
+use entity::share::ActiveModel as ShareActiveModel;
+use entity::share::Column as ShareCol;
+use entity::share::Model as ShareModel;
+use entity::user::Column as UserCol;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// look and download, nothing else
+    #[default]
+    Read,
+    /// may also put a copy into their own library
+    Copy,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Read => "read",
+            Mode::Copy => "copy",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ShareId {
+    share_id: i32,
+}
+
+/// Shares either a book or a collection, never both.
+#[derive(Deserialize)]
+struct ShareRequest {
+    username: String,
+    book_id: Option<i32>,
+    collection_id: Option<i32>,
+    #[serde(default)]
+    mode: Mode,
+}
+
+#[derive(Serialize)]
+struct ShareInfo {
+    #[serde(flatten)]
+    share: ShareModel,
+    /// who it's shared with
+    recipient: String,
+}
+
+#[derive(Serialize)]
+struct SharedBook {
+    share_id: i32,
+    /// who shared it
+    from: String,
+    mode: String,
+    /// name of the shared collection the book is in, if it came with one
+    collection: Option<String>,
+    book: FullBook,
+}
+
+/// Someone else's book `user_id` has been given, along with the share that allows it. If the
+/// book came with more than one share, one that allows copying wins.
+async fn find_shared(
+    db: &DatabaseConnection,
+    user_id: i32,
+    book_id: i32,
+) -> actix_web::Result<(ShareModel, BookModel)> {
+    let not_shared = || not_found("No such book shared with you.".to_string());
+    let book = Book::find_by_id(book_id)
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(not_shared)?;
+    let collections: Vec<i32> = CollectionBook::find()
+        .filter(CBCol::BookId.eq(book.id))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|cb| cb.collection_id)
+        .collect();
+    let share = Share::find()
+        .filter(ShareCol::RecipientId.eq(user_id))
+        .filter(ShareCol::UserId.eq(book.user_id))
+        .filter(
+            Condition::any()
+                .add(ShareCol::BookId.eq(book.id))
+                .add(ShareCol::CollectionId.is_in(collections)),
+        )
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .max_by_key(|s| s.mode == Mode::Copy.as_str())
+        .ok_or_else(not_shared)?;
+    Ok((share, book))
+}
+
+/// What the user shared with others.
+#[get("/shares")]
+async fn list(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let shares = Share::find()
+        .filter(ShareCol::UserId.eq(user.id))
+        .order_by_desc(ShareCol::CreatedAt)
+        .order_by_desc(ShareCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let names: HashMap<i32, String> = User::find()
+        .filter(UserCol::Id.is_in(shares.iter().map(|s| s.recipient_id)))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect();
+    let shares: Vec<ShareInfo> = shares
+        .into_iter()
+        .map(|share| ShareInfo {
+            recipient: names.get(&share.recipient_id).cloned().unwrap_or_default(),
+            share,
+        })
+        .collect();
+    Ok(ok(shares))
+}
+
+/// Shares a book or collection, sharing the same thing with the same user again just changes
+/// the mode.
+#[put("/shares")]
+async fn create(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<ShareRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let req_data = req_data.into_inner();
+    let recipient = User::find()
+        .filter(UserCol::Username.eq(req_data.username.trim()))
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found("No such user found.".to_string()))?;
+    if recipient.id == user.id {
+        return Err(bad_request("You can't share with yourself."));
+    }
+    let mut select = Share::find()
+        .filter(ShareCol::UserId.eq(user.id))
+        .filter(ShareCol::RecipientId.eq(recipient.id));
+    select = match (req_data.book_id, req_data.collection_id) {
+        (Some(book_id), None) => {
+            let book = (BookId { book_id })
+                .get(user.id, db)
+                .await
+                .map_err(not_found)?;
+            select.filter(ShareCol::BookId.eq(book.id))
+        }
+        (None, Some(collection_id)) => {
+            let collection = Collection::find_by_id(collection_id)
+                .filter(CCol::UserId.eq(user.id))
+                .one(db)
+                .await
+                .map_err(db_error)?
+                .ok_or_else(|| not_found("No such collection found.".to_string()))?;
+            select.filter(ShareCol::CollectionId.eq(collection.id))
+        }
+        _ => return Err(bad_request("Share either a book_id or a collection_id.")),
+    };
+
+    let share = match select.one(db).await.map_err(db_error)? {
+        Some(share) => {
+            let mut share: ShareActiveModel = share.into();
+            share.mode = ActiveValue::Set(req_data.mode.as_str().to_string());
+            share.update(db).await
+        }
+        None => {
+            ShareActiveModel {
+                id: ActiveValue::NotSet,
+                user_id: ActiveValue::Set(user.id),
+                recipient_id: ActiveValue::Set(recipient.id),
+                book_id: ActiveValue::Set(req_data.book_id),
+                collection_id: ActiveValue::Set(req_data.collection_id),
+                mode: ActiveValue::Set(req_data.mode.as_str().to_string()),
+                created_at: ActiveValue::Set(Utc::now().timestamp()),
+            }
+            .insert(db)
+            .await
+        }
+    }
+    .map_err(db_error)?;
+    Ok(ok(ShareInfo {
+        share,
+        recipient: recipient.username,
+    }))
+}
+
+/// Revokes a share, or for the recipient, gets rid of something they don't want.
+#[delete("/shares/{share_id}")]
+async fn remove(
+    path: web::Path<ShareId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let res = Share::delete_many()
+        .filter(ShareCol::Id.eq(path.share_id))
+        .filter(
+            Condition::any()
+                .add(ShareCol::UserId.eq(user.id))
+                .add(ShareCol::RecipientId.eq(user.id)),
+        )
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(res.rows_affected))
+}
+
+/// Every book others shared with the user, directly or in a collection, newest share first.
+#[get("/shared")]
+async fn shared_with_me(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let shares = Share::find()
+        .filter(ShareCol::RecipientId.eq(user.id))
+        .order_by_desc(ShareCol::CreatedAt)
+        .order_by_desc(ShareCol::Id)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let owners: HashMap<i32, String> = User::find()
+        .filter(UserCol::Id.is_in(shares.iter().map(|s| s.user_id)))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect();
+    let collection_ids: Vec<i32> = shares.iter().filter_map(|s| s.collection_id).collect();
+    let collections: HashMap<i32, String> = Collection::find()
+        .filter(CCol::Id.is_in(collection_ids.clone()))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+    let mut collection_books: HashMap<i32, Vec<i32>> = HashMap::new();
+    for cb in CollectionBook::find()
+        .filter(CBCol::CollectionId.is_in(collection_ids))
+        .order_by_asc(CBCol::Position)
+        .all(db)
+        .await
+        .map_err(db_error)?
+    {
+        collection_books
+            .entry(cb.collection_id)
+            .or_default()
+            .push(cb.book_id);
+    }
+
+    // each book once, from the share that allows the most
+    let mut entries: Vec<(i32, &ShareModel)> = vec![];
+    let mut seen: HashMap<i32, usize> = HashMap::new();
+    for share in &shares {
+        let book_ids = share.book_id.into_iter().chain(
+            share
+                .collection_id
+                .and_then(|id| collection_books.get(&id))
+                .into_iter()
+                .flatten()
+                .copied(),
+        );
+        for book_id in book_ids {
+            match seen.get(&book_id) {
+                Some(&i) if share.mode == Mode::Copy.as_str() => entries[i].1 = share,
+                Some(_) => {}
+                None => {
+                    seen.insert(book_id, entries.len());
+                    entries.push((book_id, share));
+                }
+            }
+        }
+    }
+    let books = Book::find()
+        .filter(BookCol::Id.is_in(seen.into_keys()))
+        .find_also_related(FileType)
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut books: HashMap<i32, FullBook> = full_books(db, books)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|b| (b.id, b))
+        .collect();
+    let shared: Vec<SharedBook> = entries
+        .into_iter()
+        .filter_map(|(book_id, share)| {
+            // only ever the sharer's own books, whatever ended up in their collection
+            let book = books
+                .remove(&book_id)
+                .filter(|b| b.user_id == share.user_id)?;
+            Some(SharedBook {
+                share_id: share.id,
+                from: owners.get(&share.user_id).cloned().unwrap_or_default(),
+                mode: share.mode.clone(),
+                collection: share
+                    .collection_id
+                    .and_then(|id| collections.get(&id).cloned()),
+                book,
+            })
+        })
+        .collect();
+    Ok(ok(shared))
+}
+
+#[get("/shared/book/{book_id}/dl")]
+async fn download(
+    req: HttpRequest,
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<HttpResponse> {
+    let db: &DatabaseConnection = &db;
+    let (_, book) = find_shared(db, user.id, bookid.book_id).await?;
+    let book = bookid.get(book.user_id, db).await.map_err(not_found)?;
+    serve_book(&req, &config, book).await
+}
+
+/// Adds a shared book to the user's own library. Only the database row is new, the file is the
+/// one the sharer uploaded.
+#[post("/shared/book/{book_id}/copy")]
+async fn copy(
+    bookid: web::Path<BookId>,
+    config: web::Data<Config>,
+    db: web::Data<DatabaseConnection>,
+    search: web::Data<Search>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let (share, book) = find_shared(db, user.id, bookid.book_id).await?;
+    if share.mode != Mode::Copy.as_str() {
+        return Err(error::ErrorForbidden(ErrorResponse {
+            status: "error".to_string(),
+            error: "This book was only shared for reading.".to_string(),
+        }));
+    }
+    let book_id = copy_book(&config, db, &search, user.id, book).await?;
+    let book = (BookId { book_id })
+        .get(user.id, db)
+        .await
+        .map_err(not_found)?;
+    Ok(ok(book))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(create)
+        .service(remove)
+        .service(shared_with_me)
+        .service(download)
+        .service(copy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use actix_web::test::{call_and_read_body_json, call_service, TestRequest};
+    use entity::collection::ActiveModel as CActiveModel;
+    use entity::collection_book::ActiveModel as CBActiveModel;
+    use serde_json::{json, Value};
+
+    async fn book(
+        config: &Config,
+        db: &DatabaseConnection,
+        search: &web::Data<Search>,
+        user_id: i32,
+        name: &str,
+    ) -> i32 {
+        let pdf = format!("%PDF-1.4\n% {name}\n%%EOF\n");
+        add_book(
+            config,
+            db,
+            search,
+            user_id,
+            Some(format!("{name}.pdf")),
+            pdf.into(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn share(body: Value) -> actix_http::Request {
+        TestRequest::put()
+            .uri("/api/shares")
+            .set_json(body)
+            .to_request()
+    }
+
+    #[actix_web::test]
+    async fn recipients_see_each_shared_book_once_with_the_most_it_allows() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let alice = testing::user(&db, "alice").await;
+        let bob = testing::user(&db, "bob").await;
+        let carol = testing::user(&db, "carol").await;
+        let first = book(&config, &db, &search, alice.id, "first").await;
+        let second = book(&config, &db, &search, alice.id, "second").await;
+        let private = book(&config, &db, &search, alice.id, "private").await;
+        let foreign = book(&config, &db, &search, carol.id, "foreign").await;
+        let collection = CActiveModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(alice.id),
+            name: ActiveValue::Set("Favourites".to_string()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        // somebody else's book that ended up in the collection doesn't go along with it
+        for (position, book_id) in [first, second, foreign].into_iter().enumerate() {
+            CBActiveModel {
+                id: ActiveValue::NotSet,
+                collection_id: ActiveValue::Set(collection.id),
+                book_id: ActiveValue::Set(book_id),
+                position: ActiveValue::Set(position as i32),
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        let owner = testing::app(&config, &db, &search, &alice, configure).await;
+        let res = call_service(
+            &owner,
+            share(json!({ "username": "bob", "book_id": first })),
+        )
+        .await;
+        assert_eq!(res.status(), 200);
+        let body = json!({ "username": "bob", "collection_id": collection.id, "mode": "copy" });
+        let res = call_service(&owner, share(body)).await;
+        assert_eq!(res.status(), 200);
+        let res = call_service(
+            &owner,
+            share(json!({ "username": "alice", "book_id": first })),
+        )
+        .await;
+        assert_eq!(res.status(), 400);
+
+        let recipient = testing::app(&config, &db, &search, &bob, configure).await;
+        let shared = testing::get_json(&recipient, "/api/shared").await;
+        let seen: Vec<(i64, &str)> = shared["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                (
+                    s["book"]["id"].as_i64().unwrap(),
+                    s["mode"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(seen, [(first as i64, "copy"), (second as i64, "copy")]);
+        assert_eq!(shared["data"][0]["from"], "alice");
+        assert_eq!(shared["data"][0]["collection"], "Favourites");
+
+        for unshared in [private, foreign] {
+            let req = TestRequest::get()
+                .uri(&format!("/api/shared/book/{unshared}/dl"))
+                .to_request();
+            assert_eq!(call_service(&recipient, req).await.status(), 404);
+        }
+        let copy_of = |book_id: i32| {
+            TestRequest::post()
+                .uri(&format!("/api/shared/book/{book_id}/copy"))
+                .to_request()
+        };
+        let res: Value = call_and_read_body_json(&recipient, copy_of(second)).await;
+        assert_eq!(res["data"]["user_id"], bob.id);
+        assert_ne!(res["data"]["id"], second);
+
+        // sharing the same thing again only changes what's allowed
+        let body = json!({ "username": "bob", "collection_id": collection.id, "mode": "read" });
+        let res = call_service(&owner, share(body)).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            testing::get_json(&owner, "/api/shares").await["data"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(call_service(&recipient, copy_of(first)).await.status(), 403);
+    }
+}
@@ -0,0 +1,393 @@
+use super::book::{escape_like, BookId};
+use super::{bad_request, changes, db_error, not_found, ok};
+use crate::AuthData;
+use actix_web::web;
+use actix_web::{delete, get, patch, put};
+use entity::book_tag::ActiveModel as BTActiveModel;
+use entity::book_tag::Column as BTCol;
+use entity::prelude::{BookTag, Tag};
+use entity::tag::ActiveModel as TagActiveModel;
+use entity::tag::Column as TagCol;
+use entity::tag::Model as TagModel;
+use entity::tag::Relation as TagRelation;
+use sea_orm::sea_query::{Expr, LikeExpr};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, JoinType, Order, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Deserialize)]
+struct TagId {
+    tag_id: i32,
+}
+
+#[derive(Deserialize)]
+struct BookTagName {
+    book_id: i32,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct TagQuery {
+    /// start of the tag name, for autocomplete
+    q: Option<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TagRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct TagsRequest {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct TagSummary {
+    id: i32,
+    name: String,
+    book_count: i64,
+}
+
+/// Tidies up whitespace in a tag name, `None` if nothing is left.
+pub fn tag_name(name: &str) -> Option<String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!name.is_empty()).then_some(name)
+}
+
+/// What tells tags apart, names that only differ in case belong to the same tag.
+pub fn name_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Finds the user's tags by name, ignoring case, and creates the ones they don't have yet.
+async fn get_or_create(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    names: &[String],
+) -> Result<Vec<TagModel>, DbErr> {
+    let mut tags = Tag::find()
+        .filter(TagCol::UserId.eq(user_id))
+        .filter(TagCol::NameKey.is_in(names.iter().map(|n| name_key(n))))
+        .all(db)
+        .await?;
+    let mut known: HashSet<String> = tags.iter().map(|t| t.name_key.clone()).collect();
+    for name in names {
+        if known.insert(name_key(name)) {
+            let tag = TagActiveModel {
+                id: ActiveValue::NotSet,
+                user_id: ActiveValue::Set(user_id),
+                name: ActiveValue::Set(name.clone()),
+                name_key: ActiveValue::Set(name_key(name)),
+            }
+            .insert(db)
+            .await?;
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+/// Adds tags to a book, the ones it already has are left alone.
+pub async fn tag_book(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+    book_id: i32,
+    names: &[String],
+) -> Result<(), DbErr> {
+    let names: Vec<String> = names.iter().filter_map(|n| tag_name(n)).collect();
+    if names.is_empty() {
+        return Ok(());
+    }
+    let tags = get_or_create(db, user_id, &names).await?;
+    let has: HashSet<i32> = BookTag::find()
+        .filter(BTCol::BookId.eq(book_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bt| bt.tag_id)
+        .collect();
+    let new: Vec<BTActiveModel> = tags
+        .into_iter()
+        .filter(|t| !has.contains(&t.id))
+        .map(|t| BTActiveModel {
+            id: ActiveValue::NotSet,
+            book_id: ActiveValue::Set(book_id),
+            tag_id: ActiveValue::Set(t.id),
+        })
+        .collect();
+    if !new.is_empty() {
+        BookTag::insert_many(new).exec(db).await?;
+    }
+    Ok(())
+}
+
+/// Books carrying a tag, so their change feed entries can be bumped when the tag changes.
+async fn tagged_books(db: &DatabaseConnection, tag_id: i32) -> Result<Vec<i32>, DbErr> {
+    BookTag::find()
+        .select_only()
+        .column(BTCol::BookId)
+        .filter(BTCol::TagId.eq(tag_id))
+        .into_tuple::<i32>()
+        .all(db)
+        .await
+}
+
+async fn find(db: &DatabaseConnection, uid: i32, id: i32) -> actix_web::Result<TagModel> {
+    match Tag::find_by_id(id)
+        .filter(TagCol::UserId.eq(uid))
+        .one(db)
+        .await
+    {
+        Ok(Some(t)) => Ok(t),
+        Ok(None) => Err(not_found("No such tag found.".to_string())),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+#[get("/tags")]
+async fn list(
+    query: web::Query<TagQuery>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let mut select = Tag::find()
+        .select_only()
+        .column(TagCol::Id)
+        .column(TagCol::Name)
+        .column_as(BTCol::Id.count(), "book_count")
+        .join(JoinType::LeftJoin, TagRelation::BookTag.def())
+        .filter(TagCol::UserId.eq(user.id))
+        .group_by(TagCol::Id)
+        .group_by(TagCol::Name)
+        // the ones used the most are the likeliest to be wanted again
+        .order_by(BTCol::Id.count(), Order::Desc)
+        .order_by_asc(TagCol::Name);
+    if let Some(q) = query.q.as_deref().and_then(tag_name) {
+        select = select.filter(
+            Expr::col((Tag, TagCol::NameKey))
+                .like(LikeExpr::new(format!("{}%", escape_like(&name_key(&q)))).escape('\\')),
+        );
+    }
+    if let Some(limit) = query.limit {
+        select = select.limit(limit);
+    }
+    let tags: Vec<TagSummary> = select
+        .into_tuple::<(i32, String, i64)>()
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|(id, name, book_count)| TagSummary {
+            id,
+            name,
+            book_count,
+        })
+        .collect();
+    Ok(ok(tags))
+}
+
+#[patch("/tags/{tag_id}")]
+async fn rename(
+    path: web::Path<TagId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<TagRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let tag = find(db, user.id, path.tag_id).await?;
+    let Some(name) = tag_name(&req_data.name) else {
+        return Err(bad_request("Tag name can't be empty."));
+    };
+    let books = tagged_books(db, tag.id).await.map_err(db_error)?;
+    let other = Tag::find()
+        .filter(TagCol::UserId.eq(user.id))
+        .filter(TagCol::Id.ne(tag.id))
+        .filter(TagCol::NameKey.eq(name_key(&name)))
+        .one(db)
+        .await
+        .map_err(db_error)?;
+    let renamed = match other {
+        // renaming onto a tag that already exists merges the two
+        Some(other) => {
+            for book_id in &books {
+                tag_book(db, user.id, *book_id, std::slice::from_ref(&other.name))
+                    .await
+                    .map_err(db_error)?;
+            }
+            Tag::delete_by_id(tag.id).exec(db).await.map_err(db_error)?;
+            other
+        }
+        None => {
+            let mut tag: TagActiveModel = tag.into();
+            tag.name_key = ActiveValue::Set(name_key(&name));
+            tag.name = ActiveValue::Set(name);
+            tag.update(db).await.map_err(db_error)?
+        }
+    };
+    for book_id in books {
+        changes::record(db, user.id, book_id, changes::UPDATED)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(ok(renamed))
+}
+
+#[delete("/tags/{tag_id}")]
+async fn remove(
+    path: web::Path<TagId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let tag = find(db, user.id, path.tag_id).await?;
+    let books = tagged_books(db, tag.id).await.map_err(db_error)?;
+    let res = Tag::delete_by_id(tag.id).exec(db).await.map_err(db_error)?;
+    for book_id in books {
+        changes::record(db, user.id, book_id, changes::UPDATED)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(ok(res.rows_affected))
+}
+
+#[put("/book/{book_id}/tags")]
+async fn add_to_book(
+    bookid: web::Path<BookId>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<TagsRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    tag_book(db, user.id, book.id, &req_data.tags)
+        .await
+        .map_err(db_error)?;
+    changes::record(db, user.id, book.id, changes::UPDATED)
+        .await
+        .map_err(db_error)?;
+    Ok(ok(bookid.get(user.id, db).await.map_err(not_found)?))
+}
+
+#[delete("/book/{book_id}/tags/{tag}")]
+async fn remove_from_book(
+    path: web::Path<BookTagName>,
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let db: &DatabaseConnection = &db;
+    let bookid = BookId {
+        book_id: path.book_id,
+    };
+    let book = bookid.get(user.id, db).await.map_err(not_found)?;
+    let tags: Vec<i32> = Tag::find()
+        .filter(TagCol::UserId.eq(user.id))
+        .filter(TagCol::NameKey.eq(name_key(&path.tag)))
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+    // the tag itself stays around even if no book has it anymore, it still shows up in autocomplete
+    let res = BookTag::delete_many()
+        .filter(BTCol::BookId.eq(book.id))
+        .filter(BTCol::TagId.is_in(tags))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    if res.rows_affected > 0 {
+        changes::record(db, user.id, book.id, changes::UPDATED)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(ok(bookid.get(user.id, db).await.map_err(not_found)?))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(rename)
+        .service(remove)
+        .service(add_to_book)
+        .service(remove_from_book);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::book::add_book;
+    use crate::testing;
+    use actix_web::test;
+
+    async fn tags(db: &DatabaseConnection) -> Vec<String> {
+        Tag::find()
+            .order_by_asc(TagCol::Id)
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn tags_only_differing_in_case_are_one_tag() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+        let app = testing::app(&config, &db, &search, &user, configure).await;
+        let mut books = vec![];
+        for name in ["a.pdf", "b.pdf"] {
+            let pdf = format!("%PDF-1.4\n% {name}\n%%EOF\n");
+            let id = add_book(
+                &config,
+                &db,
+                &search,
+                user.id,
+                Some(name.into()),
+                pdf.into(),
+            );
+            books.push(id.await.unwrap());
+        }
+
+        tag_book(&db, user.id, books[0], &["Sci-Fi".into()])
+            .await
+            .unwrap();
+        tag_book(&db, user.id, books[1], &[" sci-FI ".into(), "Space".into()])
+            .await
+            .unwrap();
+        assert_eq!(tags(&db).await, ["Sci-Fi", "Space"]);
+        let found = testing::get_json(&app, "/api/tags?q=SC").await;
+        assert_eq!(found["data"][0]["name"], "Sci-Fi");
+        assert_eq!(found["data"][0]["book_count"], 2);
+
+        // the database holds the line too
+        let twin = TagActiveModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(user.id),
+            name: ActiveValue::Set("SCI-FI".into()),
+            name_key: ActiveValue::Set(name_key("SCI-FI")),
+        };
+        assert!(twin.insert(&db).await.is_err());
+
+        // renaming onto another tag in different case merges them
+        let space = Tag::find()
+            .filter(TagCol::NameKey.eq("space"))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        let req = test::TestRequest::patch()
+            .uri(&format!("/api/tags/{}", space.id))
+            .set_json(serde_json::json!({ "name": "SCI-FI" }));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(res.status().is_success());
+        assert_eq!(tags(&db).await, ["Sci-Fi"]);
+    }
+}
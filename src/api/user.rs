@@ -1,13 +1,19 @@
 use crate::auth::Claims;
 use crate::config::Config;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
 use argon2::{self, hash_encoded, verify_encoded, Config as ArgonConf, Variant, Version};
 
+use super::kosync::md5_hex;
+use super::{bad_request, ok, server_error};
 use crate::AuthData;
 use crate::ErrorResponse;
 use actix_web::{get, post, put};
 use actix_web::{web, HttpResponse};
 use entity::user::{self, ActiveModel, Entity};
-use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
 use serde::{Deserialize, Serialize};
 
 pub fn hash_password(password: String, salt: String) -> String {
@@ -23,6 +29,19 @@ pub fn hash_password(password: String, salt: String) -> String {
     };
     hash_encoded(password.as_bytes(), salt.as_bytes(), &config).unwrap()
 }
+
+pub(crate) fn new_user(username: &str, password: &str) -> ActiveModel {
+    ActiveModel {
+        id: ActiveValue::NotSet,
+        username: ActiveValue::Set(username.to_string()),
+        password: ActiveValue::Set(hash_password(
+            password.to_string(),
+            "very7898952salty:)".to_string(), // Todo: make the salt reanomd
+        )),
+        admin: ActiveValue::Set(false),
+        kosync_key: ActiveValue::Set(None),
+    }
+}
 #[derive(Serialize, Deserialize)]
 pub struct Tokens {
     status: String,
@@ -69,15 +88,7 @@ async fn register(
     db: web::Data<DatabaseConnection>,
     req_data: web::Json<RegisterRequest>,
 ) -> impl actix_web::Responder {
-    let user = ActiveModel {
-        id: ActiveValue::NotSet,
-        username: ActiveValue::Set(req_data.username.clone()),
-        password: ActiveValue::Set(hash_password(
-            req_data.password.clone(),
-            "very7898952salty:)".to_string(), // Todo: make the salt reanomd
-        )),
-        admin: ActiveValue::Set(false),
-    };
+    let user = new_user(&req_data.username, &req_data.password);
     let con: &DatabaseConnection = &db;
 
     match Entity::insert(user).exec(con).await {
@@ -156,6 +167,34 @@ async fn login(
     }
 }
 
+#[derive(Deserialize)]
+pub struct KosyncRequest {
+    password: String,
+}
+
+/// Sets the password KOReader's progress sync logs in with, it's kept apart from the real one
+/// because KOReader sends it around as an unsalted md5.
+#[put("/user/@me/kosync")]
+async fn set_kosync_password(
+    db: web::Data<DatabaseConnection>,
+    AuthData(user): AuthData,
+    req_data: web::Json<KosyncRequest>,
+) -> actix_web::Result<impl actix_web::Responder> {
+    let con: &DatabaseConnection = &db;
+    if req_data.password.is_empty() {
+        return Err(bad_request("Password can't be empty."));
+    }
+    // KOReader only ever sends the md5, that's what gets hashed
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let md5 = md5_hex(req_data.password.as_bytes());
+    let key = web::block(move || hash_password(md5, hex::encode(salt))).await?;
+    let mut user: ActiveModel = user.into();
+    user.kosync_key = ActiveValue::Set(Some(key));
+    user.update(con).await.map_err(server_error)?;
+    Ok(ok(()))
+}
+
 #[get("/user/@me")]
 async fn me(AuthData(user): AuthData) -> actix_web::Result<impl actix_web::Responder> {
     Ok(HttpResponse::Ok().json(user))
@@ -166,5 +205,5 @@ pub fn configure_na(cfg: &mut web::ServiceConfig) {
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(me);
+    cfg.service(me).service(set_kosync_password);
 }
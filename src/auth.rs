@@ -57,6 +57,33 @@ impl Claims {
     }
 }
 
+/// What a public share link carries, signed like login tokens so nobody can make one up.
+/// The same link always gives the same token, so there's no need to store it.
+#[derive(Serialize, Deserialize)]
+pub struct LinkClaims {
+    pub link_id: i32,
+    pub exp: i64,
+}
+
+impl LinkClaims {
+    pub fn create_token(&self, key_path: &Path) -> Result<String, Error> {
+        let key = std::fs::read(key_path)?;
+        let enc_key = EncodingKey::from_rsa_pem(&key)
+            .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
+        encode(&Header::new(Algorithm::RS512), &self, &enc_key)
+            .map_err(|e| error::ErrorInternalServerError(e.to_string()))
+    }
+
+    pub fn from_token(token: &str, key_path: &Path) -> Result<Self, Error> {
+        let key = std::fs::read(key_path)?;
+        let dec_key = DecodingKey::from_rsa_pem(&key)
+            .map_err(|e| error::ErrorInternalServerError(e.to_string()))?;
+        decode::<Self>(token, &dec_key, &Validation::new(Algorithm::RS512))
+            .map(|data| data.claims)
+            .map_err(|e| error::ErrorNotFound(e.to_string()))
+    }
+}
+
 pub async fn validator(
     req: ServiceRequest,
     creds: BearerAuth,
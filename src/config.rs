@@ -1,3 +1,4 @@
+use aes_gcm::Aes256Gcm;
 use serde::Deserialize;
 use std::path::PathBuf;
 
@@ -6,10 +7,22 @@ pub struct Config {
     pub address: String,
     pub filepath: String,
     pub port: u16,
+    /// Where the server is reachable from outside, like `https://books.example.com`.
+    /// Links in mails are built from it, without it mails only carry codes.
+    #[serde(default)]
+    pub public_url: Option<String>,
     pub jwt: JWTConfig,
     #[serde(default)]
     pub cors: Option<CORSConfig>,
     pub db: DBConfig,
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    #[serde(default)]
+    pub ingest: Option<IngestConfig>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -30,3 +43,52 @@ pub struct DBConfig {
     pub connection_string: String,
     pub connections: u32,
 }
+
+#[derive(Deserialize, Clone)]
+pub struct EncryptionConfig {
+    /// File holding the 32 byte server key, either raw or hex encoded.
+    pub key: PathBuf,
+    /// The key from `key`, read once on startup by [`crate::storage::load_key`].
+    #[serde(skip)]
+    pub server_key: Option<Aes256Gcm>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct SearchConfig {
+    /// Where the index lives, `search-index` inside `filepath` if not set.
+    pub index_path: Option<PathBuf>,
+    /// Also index the text of epubs, makes the index a lot bigger. Not allowed with `encryption`.
+    #[serde(default)]
+    pub index_content: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// the usual port for `security` if not set
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// address books are sent from, the one to add to a kindle's approved senders
+    pub from: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// plain text, only for a relay on the same machine
+    None,
+    #[default]
+    StartTls,
+    Tls,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IngestConfig {
+    /// maildir the mail server delivers the ingest address to
+    pub maildir: PathBuf,
+    /// seconds between looking for new mail, 60 if not set
+    pub interval: Option<u64>,
+}
@@ -0,0 +1,287 @@
+use crate::config::Config;
+use crate::storage;
+use font8x8::legacy::{BASIC_LEGACY, LATIN_LEGACY};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, ImageEncoder, Rgb, RgbImage};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Size {
+    /// library tiles
+    Small,
+    /// book details
+    Medium,
+    Large,
+    /// whatever was in the book
+    #[default]
+    Original,
+}
+
+impl Size {
+    /// Width of the thumbnail, the height follows the aspect ratio.
+    fn width(self) -> Option<u32> {
+        match self {
+            Size::Small => Some(128),
+            Size::Medium => Some(320),
+            Size::Large => Some(640),
+            Size::Original => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Size::Small => "small",
+            Size::Medium => "medium",
+            Size::Large => "large",
+            Size::Original => "original",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Jpeg,
+    Webp,
+}
+
+impl Format {
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Jpeg => "image/jpeg",
+            Format::Webp => "image/webp",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpeg",
+            Format::Webp => "webp",
+        }
+    }
+}
+
+/// Blob name of a cached thumbnail, `cover` is the blob name of the full size cover without `.bin`.
+pub fn thumbnail_name(cover: &str, size: Size, format: Format) -> String {
+    format!("{}-{}-{}.bin", cover, size.name(), format.name())
+}
+
+/// Drops every cached thumbnail of a cover, for when the cover itself changes.
+pub fn remove_thumbnails(config: &Config, cover: &str) -> io::Result<()> {
+    for size in [Size::Small, Size::Medium, Size::Large] {
+        for format in [Format::Jpeg, Format::Webp] {
+            storage::remove(config, &thumbnail_name(cover, size, format))?;
+        }
+    }
+    Ok(())
+}
+
+/// Scales a cover down to `size` and encodes it as `format`.
+pub fn thumbnail(data: &[u8], size: Size, format: Format) -> image::ImageResult<Vec<u8>> {
+    let cover = image::load_from_memory(data)?;
+    let cover = match size.width() {
+        // never blow small covers up
+        Some(width) if width < cover.width() => cover.resize(width, u32::MAX, FilterType::Triangle),
+        _ => cover,
+    };
+    let mut out = vec![];
+    match format {
+        Format::Jpeg => {
+            let rgb = cover.to_rgb8();
+            JpegEncoder::new_with_quality(&mut out, 85).write_image(
+                &rgb,
+                rgb.width(),
+                rgb.height(),
+                ColorType::Rgb8,
+            )?;
+        }
+        Format::Webp => {
+            let rgba = cover.to_rgba8();
+            WebPEncoder::new_lossless(&mut out).write_image(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+/// Returns the cached thumbnail of a cover, generating it on the first request.
+pub fn cached_thumbnail(
+    config: &Config,
+    cover: &str,
+    size: Size,
+    format: Format,
+) -> io::Result<Vec<u8>> {
+    let name = thumbnail_name(cover, size, format);
+    if storage::exists(config, &name) {
+        return storage::read(config, &name);
+    }
+    let original = storage::read(config, &format!("{cover}.bin"))?;
+    let thumb = thumbnail(&original, size, format)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    storage::write(config, &name, &thumb)?;
+    Ok(thumb)
+}
+
+const PLACEHOLDER_WIDTH: u32 = 600;
+const PLACEHOLDER_HEIGHT: u32 = 900;
+const MARGIN: u32 = 48;
+
+/// Blob name (without `.bin`) of the generated cover for a book, edits to the title or
+/// author get a new one.
+pub fn placeholder_name(hash: &str, title: &str, creator: &str) -> String {
+    let text = hex::encode(Sha256::digest(format!("{title}\0{creator}")));
+    format!("{}-placeholder-{}", hash, &text[..16])
+}
+
+fn glyph(c: char) -> [u8; 8] {
+    match c as u32 {
+        0..=0x7F => BASIC_LEGACY[c as usize],
+        0xA0..=0xFF => LATIN_LEGACY[c as usize - 0xA0],
+        _ => BASIC_LEGACY['?' as usize],
+    }
+}
+
+/// Greedy word wrap, words longer than a line get cut.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let word: String = word.chars().take(width).collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            let keep = last.chars().count().min(width - 3);
+            *last = format!("{}...", last.chars().take(keep).collect::<String>());
+        }
+    }
+    lines
+}
+
+/// Draws centered lines of text starting at `y`, returns where the next line would go.
+fn draw_lines(img: &mut RgbImage, lines: &[String], mut y: u32, scale: u32, color: Rgb<u8>) -> u32 {
+    for line in lines {
+        let width = line.chars().count() as u32 * 8 * scale;
+        let mut x = PLACEHOLDER_WIDTH.saturating_sub(width) / 2;
+        for c in line.chars() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..8 {
+                    if bits & (1 << col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let (px, py) = (x + col * scale + dx, y + row as u32 * scale + dy);
+                            if px < img.width() && py < img.height() {
+                                img.put_pixel(px, py, color);
+                            }
+                        }
+                    }
+                }
+            }
+            x += 8 * scale;
+        }
+        y += 10 * scale;
+    }
+    y
+}
+
+/// hsl with fixed saturation and lightness so every color is dark enough for white text
+fn background(hash: &str) -> Rgb<u8> {
+    let hue = u32::from_str_radix(hash.get(..4).unwrap_or("0"), 16).unwrap_or(0) % 360;
+    let (s, l) = (0.45, 0.32);
+    let c = (1.0 - (2.0 * l - 1.0_f64).abs()) * s;
+    let h = hue as f64 / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue / 60 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    let channel = |v: f64| ((v + m) * 255.0).round() as u8;
+    Rgb([channel(r), channel(g), channel(b)])
+}
+
+/// Renders a plain cover with the title and author over a color picked from the hash.
+pub fn placeholder(hash: &str, title: &str, creator: &str) -> image::ImageResult<Vec<u8>> {
+    let bg = background(hash);
+    let mut img = RgbImage::from_pixel(PLACEHOLDER_WIDTH, PLACEHOLDER_HEIGHT, bg);
+
+    // a lighter frame so it reads as a book cover and not a color swatch
+    let frame = Rgb(bg.0.map(|v| v.saturating_add(60)));
+    for y in 0..PLACEHOLDER_HEIGHT {
+        for x in 0..PLACEHOLDER_WIDTH {
+            let edge = x
+                .min(y)
+                .min(PLACEHOLDER_WIDTH - 1 - x)
+                .min(PLACEHOLDER_HEIGHT - 1 - y);
+            if (20..26).contains(&edge) {
+                img.put_pixel(x, y, frame);
+            }
+        }
+    }
+
+    let white = Rgb([255, 255, 255]);
+    let text_width = (PLACEHOLDER_WIDTH - 2 * MARGIN) as usize;
+    let title_lines = wrap(title, text_width / (8 * 5), 7);
+    draw_lines(&mut img, &title_lines, MARGIN * 3, 5, white);
+    let creator_lines = wrap(creator, text_width / (8 * 3), 3);
+    let creator_height = creator_lines.len() as u32 * 10 * 3;
+    draw_lines(
+        &mut img,
+        &creator_lines,
+        PLACEHOLDER_HEIGHT - MARGIN * 2 - creator_height,
+        3,
+        Rgb(frame.0.map(|v| v.saturating_add(60))),
+    );
+
+    let mut out = vec![];
+    PngEncoder::new(&mut out).write_image(
+        &img,
+        PLACEHOLDER_WIDTH,
+        PLACEHOLDER_HEIGHT,
+        ColorType::Rgb8,
+    )?;
+    Ok(out)
+}
+
+/// Makes sure the generated cover exists in the store and returns its blob name without `.bin`.
+pub fn cached_placeholder(
+    config: &Config,
+    hash: &str,
+    title: &str,
+    creator: &str,
+) -> io::Result<String> {
+    let name = placeholder_name(hash, title, creator);
+    if !storage::exists(config, &format!("{name}.bin")) {
+        let png = placeholder(hash, title, creator)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        storage::write(config, &format!("{name}.bin"), &png)?;
+    }
+    Ok(name)
+}
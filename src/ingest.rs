@@ -0,0 +1,221 @@
+//! Books mailed to the server. The mail server delivers to a maildir, we pick new mails up from
+//! there and add their attachments to the library of whoever verified the sending address.
+
+use crate::api::book::add_book;
+use crate::api::db_error;
+use crate::config::Config;
+use crate::metadata::detect;
+use crate::search::Search;
+use actix_web::web::{self, Data};
+use entity::book::Column as BookCol;
+use entity::email::Column as EmailCol;
+use entity::prelude::{Book, Email};
+use hex::encode;
+use log::{info, warn};
+use mail_parser::{MessageParser, MimeHeaders};
+use sea_orm::sea_query::{Expr, Func};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+/// Polls the configured maildir until the server stops, does nothing without one.
+pub async fn run(config: Config, db: DatabaseConnection, search: Data<Search>) {
+    let Some(ingest) = config.ingest.clone() else {
+        return;
+    };
+    for dir in ["tmp", "new", "cur"] {
+        if let Err(e) = fs::create_dir_all(ingest.maildir.join(dir)) {
+            warn!("Couldn't set up the maildir {:?}: {}", ingest.maildir, e);
+            return;
+        }
+    }
+    let interval = Duration::from_secs(ingest.interval.unwrap_or(60).max(1));
+    loop {
+        if let Err(e) = poll(&config, &db, &search, &ingest.maildir).await {
+            warn!("Couldn't check {:?} for mail: {}", ingest.maildir, e);
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+/// Takes in everything in `new` and files what worked under `cur` as seen, so it's only looked
+/// at once. Mails that failed stay in `new` and get another go next time, which skips the books
+/// that made it in before.
+async fn poll(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &Data<Search>,
+    maildir: &Path,
+) -> io::Result<()> {
+    let mut mails: Vec<PathBuf> = fs::read_dir(maildir.join("new"))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    // maildir names start with the delivery time
+    mails.sort();
+    for path in mails {
+        let raw = match fs::read(&path) {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn!("Couldn't read the mail {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if let Err(e) = ingest(config, db, search, &raw).await {
+            warn!(
+                "Couldn't take in the mail {:?}, trying again later: {}",
+                path, e
+            );
+            continue;
+        }
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let seen = format!("{}:2,S", name.to_string_lossy());
+        fs::rename(&path, maildir.join("cur").join(seen))?;
+    }
+    Ok(())
+}
+
+async fn ingest(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &Data<Search>,
+    raw: &[u8],
+) -> actix_web::Result<()> {
+    let Some(message) = MessageParser::default().parse(raw) else {
+        warn!("Skipping a mail that isn't one.");
+        return Ok(());
+    };
+    let Some(sender) = message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|addr| addr.address())
+    else {
+        warn!("Skipping a mail without a sender.");
+        return Ok(());
+    };
+    // everyone who proved they own the address, usually just the one user
+    let users: BTreeSet<i32> = Email::find()
+        .filter(Expr::expr(Func::lower(Expr::col(EmailCol::Email))).eq(sender.to_lowercase()))
+        .filter(EmailCol::VerifiedAt.is_not_null())
+        .all(db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|email| email.user_id)
+        .collect();
+    if users.is_empty() {
+        info!(
+            "Ignoring mail from {}, nobody verified that address.",
+            sender
+        );
+        return Ok(());
+    }
+
+    for attachment in message.attachments() {
+        let data = attachment.contents().to_vec();
+        let (data, book) = web::block(move || {
+            // signatures, logos and whatever else isn't a book
+            let book = detect::detect(&data).map(|_| encode(Sha256::digest(&data)));
+            (data, book)
+        })
+        .await?;
+        let Some(hash) = book else {
+            continue;
+        };
+        let name = attachment.attachment_name().map(str::to_string);
+        let shown = name.as_deref().unwrap_or("an attachment").to_string();
+        for user_id in &users {
+            // from an earlier go at this mail, or just sent twice
+            let added = Book::find()
+                .filter(BookCol::UserId.eq(*user_id))
+                .filter(BookCol::Hash.eq(&hash))
+                .count(db)
+                .await
+                .map_err(db_error)?;
+            if added > 0 {
+                info!("Skipping {} from {}, it's there already.", shown, sender);
+                continue;
+            }
+            match add_book(config, db, search, *user_id, name.clone(), data.clone()).await {
+                Ok(book_id) => info!("Added {} from {} as book {}.", shown, sender, book_id),
+                // on our end, worth another try
+                Err(e) if e.as_response_error().status_code().is_server_error() => return Err(e),
+                // wouldn't work next time either
+                Err(e) => warn!("Couldn't add {} from {}: {}", shown, sender, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use entity::book::Model as BookModel;
+    use entity::email::ActiveModel as EmailActiveModel;
+    use sea_orm::{ActiveModelTrait, ActiveValue, QueryOrder};
+
+    /// From Reader@Example.com, two pdfs and a signature.
+    const BOOKS: &[u8] = include_bytes!("testdata/books.eml");
+
+    async fn library(db: &DatabaseConnection, user_id: i32) -> Vec<BookModel> {
+        Book::find()
+            .filter(BookCol::UserId.eq(user_id))
+            .order_by_asc(BookCol::Id)
+            .all(db)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn only_verified_senders_get_books_once() {
+        let (config, _dir) = testing::config();
+        let db = testing::db().await;
+        let search = testing::search(&config);
+        let user = testing::user(&db, "reader").await;
+
+        // nobody added the address
+        ingest(&config, &db, &search, BOOKS).await.unwrap();
+        assert!(library(&db, user.id).await.is_empty());
+
+        // added, but anybody can claim an address
+        let email = EmailActiveModel {
+            id: ActiveValue::NotSet,
+            email: ActiveValue::Set("reader@example.com".to_string()),
+            user_id: ActiveValue::Set(user.id),
+            verified_at: ActiveValue::Set(None),
+            verification_token: ActiveValue::Set(Some("ABCDEFGH".to_string())),
+            verification_expires_at: ActiveValue::Set(None),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        ingest(&config, &db, &search, BOOKS).await.unwrap();
+        assert!(library(&db, user.id).await.is_empty());
+
+        let mut email: EmailActiveModel = email.into();
+        email.verified_at = ActiveValue::Set(Some(1_716_000_000));
+        email.update(&db).await.unwrap();
+        ingest(&config, &db, &search, BOOKS).await.unwrap();
+        let names = |books: Vec<BookModel>| {
+            books
+                .into_iter()
+                .map(|b| b.file_name.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(library(&db, user.id).await),
+            ["first.pdf", "second.pdf"]
+        );
+
+        // taking the same mail in again, like after a failure halfway through, adds nothing
+        ingest(&config, &db, &search, BOOKS).await.unwrap();
+        assert_eq!(library(&db, user.id).await.len(), 2);
+    }
+}
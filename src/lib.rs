@@ -6,17 +6,45 @@ use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::ResponseError;
 use futures::future::{ready, Ready};
-use log::error;
 use serde::Serialize;
 use thiserror::Error;
 pub mod api;
 pub mod auth;
 pub mod config;
+pub mod covers;
+pub mod ingest;
+pub mod mail;
+pub mod metadata;
+pub mod search;
+pub mod storage;
+#[cfg(test)]
+mod testing;
 
 #[derive(Debug, Serialize)]
 pub struct Response<T: Serialize> {
     status: String,
     data: T,
+    /// how many items there are in total when `data` is just one page of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<u64>,
+}
+
+impl<T: Serialize> Response<T> {
+    pub fn new(data: T) -> Self {
+        Response {
+            status: "ok".to_string(),
+            data,
+            total: None,
+        }
+    }
+
+    /// `data` is one page out of `total` items
+    pub fn page(data: T, total: u64) -> Self {
+        Response {
+            total: Some(total),
+            ..Response::new(data)
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -37,7 +65,7 @@ impl FromRequest for AuthData {
 
     type Future = Ready<Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _payload: &mut actix_http::Payload) -> Self::Future {
-        ready(req.extensions().get().map(Self::clone).ok_or(Unauthorized))
+        ready(req.extensions().get().cloned().ok_or(Unauthorized))
     }
 }
 
@@ -0,0 +1,80 @@
+use crate::config::{Config, SmtpConfig, SmtpSecurity};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("This server isn't set up to send mail.")]
+    NotConfigured,
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Couldn't put the mail together: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("The mail server didn't take the mail: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// A file to attach to a mail.
+pub struct File {
+    pub name: String,
+    pub mime: Option<String>,
+    pub data: Vec<u8>,
+}
+
+fn transport(config: &SmtpConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, MailError> {
+    let mut builder = match config.security {
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        SmtpSecurity::StartTls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+        }
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+    };
+    if let Some(port) = config.port {
+        builder = builder.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+pub fn is_address(address: &str) -> bool {
+    address.parse::<Mailbox>().is_ok()
+}
+
+/// Sends a plain text mail through the server from the config, with a file attached if given.
+pub async fn send(
+    config: &Config,
+    to: &str,
+    subject: &str,
+    body: String,
+    attachment: Option<File>,
+) -> Result<(), MailError> {
+    let Some(smtp) = &config.smtp else {
+        return Err(MailError::NotConfigured);
+    };
+    let builder = Message::builder()
+        .from(smtp.from.parse()?)
+        .to(to.parse()?)
+        .subject(subject);
+    let text = SinglePart::plain(body);
+    let message = match attachment {
+        Some(file) => {
+            let content_type = file
+                .mime
+                .and_then(|mime| ContentType::parse(&mime).ok())
+                .unwrap_or_else(|| ContentType::parse("application/octet-stream").unwrap());
+            builder.multipart(
+                MultiPart::mixed()
+                    .singlepart(text)
+                    .singlepart(Attachment::new(file.name).body(file.data, content_type)),
+            )?
+        }
+        None => builder.singlepart(text)?,
+    };
+    transport(smtp)?.send(message).await?;
+    Ok(())
+}
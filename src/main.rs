@@ -1,10 +1,10 @@
 use actix_cors::Cors;
 use actix_web::{http::header, middleware, web::Data, App, HttpServer};
-use log::{debug, info};
+use log::{debug, error, info};
 use migration::MigratorTrait;
 use sea_orm::{Database, DatabaseConnection};
 use std::{env, str::FromStr};
-use stoka::{api, config::Config};
+use stoka::{api, config::Config, ingest, search::Search, storage};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -16,14 +16,25 @@ async fn main() -> std::io::Result<()> {
             .unwrap_or(log::LevelFilter::Trace),
         )
         .filter_module("sqlx::query", log::LevelFilter::Warn)
+        .filter_module("tantivy", log::LevelFilter::Warn)
         .init();
     debug!("Initalized logger!");
     let conf_path = "config.json";
     info!("Looking for config.json in current directory.");
-    let config: Config = {
+    let mut config: Config = {
         let conf = std::fs::read_to_string(conf_path)?;
         serde_json::from_str(&conf)?
     };
+    storage::load_key(&mut config)?;
+    let command = env::args().nth(1);
+    match command.as_deref() {
+        None | Some("serve") | Some("reindex") => {}
+        Some("encrypt-store") => return storage::encrypt_store(&config),
+        Some(cmd) => {
+            error!("Unknown command `{cmd}`, expected `serve`, `encrypt-store` or `reindex`.");
+            std::process::exit(1);
+        }
+    }
     let db_string = config.db.connection_string.clone();
     let cors = config.cors.clone();
     let port = config.port;
@@ -31,11 +42,48 @@ async fn main() -> std::io::Result<()> {
     let db: DatabaseConnection = Database::connect(db_string)
         .await
         .expect("Failed to create a database connection.");
+    let pending = migration::Migrator::get_pending_migrations(&db)
+        .await
+        .expect("Failed to look up pending migrations.");
     migration::Migrator::up(&db, None).await.unwrap();
+    // columns that can only be filled in from the stored files get filled once,
+    // right after the migration that added them
+    let just_applied = |name: &str| pending.iter().any(|m| m.name() == name);
+    if just_applied("m20240210_143000_book_size") {
+        if let Err(e) = api::book::backfill_sizes(&config, &db).await {
+            error!("Failed to backfill book sizes: {}", e);
+        }
+    }
+    if just_applied("m20240224_120000_book_timestamps") {
+        if let Err(e) = api::book::backfill_timestamps(&config, &db).await {
+            error!("Failed to backfill book timestamps: {}", e);
+        }
+    }
+    if just_applied("m20240330_120000_kosync") {
+        if let Err(e) = api::book::backfill_partial_md5s(&config, &db).await {
+            error!("Failed to backfill kosync document hashes: {}", e);
+        }
+    }
+    let search = Search::open(&config).expect("Failed to open the search index.");
+    // a fresh index (first start or deleted by hand) gets filled from the database
+    if command.as_deref() == Some("reindex") || search.is_empty() {
+        info!("Building the search index.");
+        stoka::search::rebuild(&search, &config, &db)
+            .await
+            .expect("Failed to build the search index.");
+        if command.is_some_and(|c| c == "reindex") {
+            return Ok(());
+        }
+    }
+    let search = Data::new(search);
+    if config.ingest.is_some() {
+        actix_web::rt::spawn(ingest::run(config.clone(), db.clone(), search.clone()));
+    }
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(config.clone()))
             .app_data(Data::new(db.clone()))
+            .app_data(search.clone())
             .wrap({
                 if let Some(cors_conf) = &cors {
                     let cors = Cors::default()
@@ -0,0 +1,249 @@
+use crate::config::Config;
+use crate::storage;
+use entity::book_info::ActiveModel as BookInfoActiveModel;
+use entity::book_info::{Identifier, Identifiers, Subjects};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use sea_orm::ActiveValue;
+use std::collections::HashMap;
+
+pub mod cbz;
+pub mod detect;
+pub mod epub;
+pub mod fb2;
+pub mod mobi;
+pub mod pdf;
+
+/// Everything we know how to pull out of a book file.
+#[derive(Default, Debug)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub published: Option<String>,
+    pub identifiers: Vec<Identifier>,
+    pub subjects: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    /// cover image and its mime type
+    pub cover: Option<(Vec<u8>, String)>,
+}
+
+pub trait MetadataExtractor {
+    /// Returns `None` if the data couldn't be parsed as this format at all.
+    fn extract(&self, data: &[u8]) -> Option<Metadata>;
+}
+
+/// Picks the extractor for a file type, `None` if we can't read that format.
+pub fn extractor(file_type: &str) -> Option<Box<dyn MetadataExtractor>> {
+    match file_type {
+        "epub" => Some(Box::new(epub::Epub)),
+        "pdf" => Some(Box::new(pdf::Pdf)),
+        "fb2" => Some(Box::new(fb2::Fb2)),
+        "mobi" | "azw" | "azw3" | "prc" => Some(Box::new(mobi::Mobi)),
+        "cbz" => Some(Box::new(cbz::Cbz)),
+        _ => None,
+    }
+}
+
+impl Metadata {
+    /// Saves the cover next to the book and turns the rest into a `BookInfo` row.
+    pub fn into_book_info(
+        self,
+        config: &Config,
+        hash: &str,
+        fallback_title: &str,
+    ) -> std::io::Result<BookInfoActiveModel> {
+        let cover_mime = match self.cover {
+            Some((data, mime)) => {
+                storage::write(config, &format!("{}-cover.bin", hash), &data)?;
+                Some(mime)
+            }
+            None => None,
+        };
+        Ok(BookInfoActiveModel {
+            id: ActiveValue::NotSet,
+            book_hash: ActiveValue::Set(hash.to_string()),
+            title: ActiveValue::Set(self.title.unwrap_or_else(|| fallback_title.to_string())),
+            creator: ActiveValue::Set(self.creators.join(", ")),
+            cover_mime: ActiveValue::Set(cover_mime),
+            language: ActiveValue::Set(self.language),
+            publisher: ActiveValue::Set(self.publisher),
+            description: ActiveValue::Set(self.description),
+            published: ActiveValue::Set(self.published),
+            identifiers: ActiveValue::Set(
+                (!self.identifiers.is_empty()).then_some(Identifiers(self.identifiers)),
+            ),
+            subjects: ActiveValue::Set(
+                (!self.subjects.is_empty()).then_some(Subjects(self.subjects)),
+            ),
+            series: ActiveValue::Set(self.series),
+            series_index: ActiveValue::Set(self.series_index),
+        })
+    }
+}
+
+/// Trims a value and throws it away if nothing is left.
+pub(crate) fn clean(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Guesses what kind of identifier this is from the value alone.
+pub fn identifier(value: String) -> Identifier {
+    let lower = value.to_lowercase();
+    for (prefix, scheme) in [
+        ("urn:uuid:", "uuid"),
+        ("urn:isbn:", "isbn"),
+        ("isbn:", "isbn"),
+    ] {
+        if lower.starts_with(prefix) {
+            return Identifier {
+                scheme: Some(scheme.to_string()),
+                value: value[prefix.len()..].to_string(),
+            };
+        }
+    }
+    let digits: String = value.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let scheme = if (digits.len() == 10 || digits.len() == 13)
+        && digits
+            .char_indices()
+            // the check digit of an ISBN-10 can be X, ISBN-13s are digits all the way
+            .all(|(i, c)| {
+                c.is_ascii_digit() || (i == 9 && digits.len() == 10 && c.eq_ignore_ascii_case(&'X'))
+            }) {
+        Some("isbn".to_string())
+    } else if value.len() == 36 && value.split('-').map(str::len).eq([8, 4, 4, 4, 12]) {
+        Some("uuid".to_string())
+    } else if lower.starts_with("urn:") {
+        lower.split(':').nth(1).map(str::to_string)
+    } else {
+        None
+    };
+    Identifier { scheme, value }
+}
+
+/// Sniffs the mime type of an image from its first few bytes.
+pub fn image_mime(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Walks an xml document and calls `f` for every element once it's closed, with the path of
+/// local element names leading to it, its attributes and the text directly inside of it.
+pub(crate) fn walk_xml(
+    data: &[u8],
+    mut f: impl FnMut(&[String], &HashMap<String, String>, &str),
+) -> Option<()> {
+    let mut reader = Reader::from_reader(data);
+    reader.trim_text(true);
+    let mut buf = vec![];
+    let mut stack: Vec<(HashMap<String, String>, String)> = vec![];
+    let mut path: Vec<String> = vec![];
+    loop {
+        let event = reader.read_event_into(&mut buf).ok()?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let name = reader
+                    .decoder()
+                    .decode(e.local_name().as_ref())
+                    .ok()?
+                    .to_string();
+                let attrs = e
+                    .attributes()
+                    .flatten()
+                    .filter_map(|a| {
+                        let key = reader
+                            .decoder()
+                            .decode(a.key.local_name().as_ref())
+                            .ok()?
+                            .to_string();
+                        let value = a.decode_and_unescape_value(&reader).ok()?;
+                        Some((key, value.to_string()))
+                    })
+                    .collect();
+                path.push(name);
+                if matches!(event, Event::Empty(_)) {
+                    f(&path, &attrs, "");
+                    path.pop();
+                } else {
+                    stack.push((attrs, String::new()));
+                }
+            }
+            Event::Text(e) => {
+                if let Some((_, text)) = stack.last_mut() {
+                    text.push_str(&e.unescape().ok()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some((_, text)) = stack.last_mut() {
+                    text.push_str(&reader.decoder().decode(&e).ok()?);
+                }
+            }
+            Event::End(_) => {
+                let (attrs, text) = stack.pop()?;
+                f(&path, &attrs, &text);
+                path.pop();
+            }
+            Event::Eof => return Some(()),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheme(value: &str) -> Option<String> {
+        identifier(value.to_string()).scheme
+    }
+
+    #[test]
+    fn prefixes_are_stripped() {
+        let id = identifier("urn:isbn:9780306406157".to_string());
+        assert_eq!(id.scheme.as_deref(), Some("isbn"));
+        assert_eq!(id.value, "9780306406157");
+        let id = identifier("ISBN:0-306-40615-2".to_string());
+        assert_eq!(id.scheme.as_deref(), Some("isbn"));
+        assert_eq!(id.value, "0-306-40615-2");
+        let id = identifier("urn:uuid:123e4567-e89b-12d3-a456-426614174000".to_string());
+        assert_eq!(id.scheme.as_deref(), Some("uuid"));
+        assert_eq!(id.value, "123e4567-e89b-12d3-a456-426614174000");
+    }
+
+    #[test]
+    fn bare_isbns() {
+        assert_eq!(scheme("9780306406157").as_deref(), Some("isbn"));
+        assert_eq!(scheme("978-0-306-40615-7").as_deref(), Some("isbn"));
+        assert_eq!(scheme("0 306 40615 2").as_deref(), Some("isbn"));
+        assert_eq!(scheme("080442957X").as_deref(), Some("isbn"));
+        assert_eq!(scheme("0-8044-2957-x").as_deref(), Some("isbn"));
+    }
+
+    #[test]
+    fn x_only_ends_an_isbn_10() {
+        assert_eq!(scheme("X804429570"), None);
+        assert_eq!(scheme("978030640X157"), None);
+        assert_eq!(scheme("978030640615X"), None);
+        assert_eq!(scheme("12345"), None);
+    }
+
+    #[test]
+    fn bare_uuids_and_other_urns() {
+        assert_eq!(
+            scheme("123e4567-e89b-12d3-a456-426614174000").as_deref(),
+            Some("uuid")
+        );
+        assert_eq!(scheme("urn:doi:10.1000/182").as_deref(), Some("doi"));
+        assert_eq!(scheme("some calibre id"), None);
+    }
+}
@@ -0,0 +1,78 @@
+use super::{clean, image_mime, walk_xml, Metadata, MetadataExtractor};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+pub struct Cbz;
+
+impl MetadataExtractor for Cbz {
+    fn extract(&self, data: &[u8]) -> Option<Metadata> {
+        let mut zip = ZipArchive::new(Cursor::new(data)).ok()?;
+        let mut meta = Metadata::default();
+
+        // ComicInfo.xml is what comicrack (and everyone copying it) writes
+        let info_name = zip
+            .file_names()
+            .find(|n| n.eq_ignore_ascii_case("comicinfo.xml"))
+            .map(str::to_string);
+        if let Some(name) = info_name {
+            let mut xml = vec![];
+            zip.by_name(&name).ok()?.read_to_end(&mut xml).ok()?;
+            let (mut year, mut month, mut day) = (None, None, None);
+            walk_xml(&xml, |path, _, text| {
+                if path.len() != 2 {
+                    return;
+                }
+                let value = clean(text);
+                match path[1].as_str() {
+                    "Title" => meta.title = value,
+                    "Series" => meta.series = value,
+                    "Number" => meta.series_index = value.and_then(|n| n.parse().ok()),
+                    "Writer" => {
+                        meta.creators = text.split(',').filter_map(clean).collect();
+                    }
+                    "Summary" => meta.description = value,
+                    "Publisher" => meta.publisher = value,
+                    "LanguageISO" => meta.language = value,
+                    "Genre" | "Tags" => meta.subjects.extend(text.split(',').filter_map(clean)),
+                    "GTIN" => meta.identifiers.extend(value.map(super::identifier)),
+                    "Year" => year = value,
+                    "Month" => month = value.and_then(|m| m.parse::<u8>().ok()),
+                    "Day" => day = value.and_then(|d| d.parse::<u8>().ok()),
+                    _ => {}
+                }
+            });
+            meta.published = match (year, month, day) {
+                (Some(y), Some(m), Some(d)) => Some(format!("{y}-{m:02}-{d:02}")),
+                (Some(y), Some(m), None) => Some(format!("{y}-{m:02}")),
+                (y, _, _) => y,
+            };
+            // a lot of comics only have the series and issue number
+            if meta.title.is_none() {
+                meta.title = match (&meta.series, meta.series_index) {
+                    (Some(s), Some(i)) => Some(format!("{s} #{i}")),
+                    (s, _) => s.clone(),
+                };
+            }
+        }
+
+        // the first page is the cover
+        let mut pages: Vec<String> = zip
+            .file_names()
+            .filter(|n| {
+                let n = n.to_lowercase();
+                !n.starts_with("__macosx")
+                    && [".jpg", ".jpeg", ".png", ".gif", ".webp"]
+                        .iter()
+                        .any(|ext| n.ends_with(ext))
+            })
+            .map(str::to_string)
+            .collect();
+        pages.sort();
+        if let Some(first) = pages.first() {
+            let mut image = vec![];
+            zip.by_name(first).ok()?.read_to_end(&mut image).ok()?;
+            meta.cover = image_mime(&image).map(|mime| (image, mime.to_string()));
+        }
+        Some(meta)
+    }
+}
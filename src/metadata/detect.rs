@@ -0,0 +1,248 @@
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// What an uploaded file actually is, going by its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detected {
+    /// the name we store in `file_type`, doubles as the file extension
+    pub name: &'static str,
+    pub mime: &'static str,
+}
+
+const EPUB: Detected = Detected {
+    name: "epub",
+    mime: "application/epub+zip",
+};
+const PDF: Detected = Detected {
+    name: "pdf",
+    mime: "application/pdf",
+};
+const MOBI: Detected = Detected {
+    name: "mobi",
+    mime: "application/x-mobipocket-ebook",
+};
+const AZW3: Detected = Detected {
+    name: "azw3",
+    mime: "application/vnd.amazon.mobi8-ebook",
+};
+const FB2: Detected = Detected {
+    name: "fb2",
+    mime: "application/x-fictionbook+xml",
+};
+const CBZ: Detected = Detected {
+    name: "cbz",
+    mime: "application/vnd.comicbook+zip",
+};
+const CBR: Detected = Detected {
+    name: "cbr",
+    mime: "application/vnd.comicbook-rar",
+};
+const DJVU: Detected = Detected {
+    name: "djvu",
+    mime: "image/vnd.djvu",
+};
+
+const IMAGE_EXTENSIONS: [&str; 5] = [".jpg", ".jpeg", ".png", ".gif", ".webp"];
+
+/// Sniffs magic bytes and container structure, `None` if it's nothing we know.
+pub fn detect(data: &[u8]) -> Option<Detected> {
+    match data {
+        [b'%', b'P', b'D', b'F', ..] => Some(PDF),
+        [b'A', b'T', b'&', b'T', b'F', b'O', b'R', b'M', ..] => Some(DJVU),
+        [b'R', b'a', b'r', b'!', 0x1A, 0x07, ..] => Some(CBR),
+        [b'P', b'K', 0x03, 0x04, ..] => detect_zip(data),
+        _ if data.get(60..68) == Some(b"BOOKMOBI") => Some(detect_mobi(data)),
+        _ if xml_root(data).as_deref() == Some("FictionBook") => Some(FB2),
+        _ => None,
+    }
+}
+
+fn detect_zip(data: &[u8]) -> Option<Detected> {
+    let mut zip = ZipArchive::new(Cursor::new(data)).ok()?;
+    // epubs have to start with an uncompressed `mimetype` entry, some tools get the order wrong
+    // so we don't insist on it being first
+    if let Ok(mut entry) = zip.by_name("mimetype") {
+        let mut mimetype = String::new();
+        entry.read_to_string(&mut mimetype).ok()?;
+        if mimetype.trim() == EPUB.mime {
+            return Some(EPUB);
+        }
+    }
+    let mut files = zip.file_names().filter(|n| !n.ends_with('/')).peekable();
+    files.peek()?;
+    // a comic is a zip full of pictures, with maybe a ComicInfo.xml thrown in
+    files
+        .all(|n| {
+            let n = n.to_lowercase();
+            IMAGE_EXTENSIONS.iter().any(|ext| n.ends_with(ext))
+                || n.ends_with(".xml")
+                || n.starts_with("__macosx")
+                || n.ends_with("thumbs.db")
+        })
+        .then_some(CBZ)
+}
+
+fn detect_mobi(data: &[u8]) -> Detected {
+    // record 0 offset is the first entry in the record list, the mobi version lives at +36
+    let version = data
+        .get(78..82)
+        .map(|off| u32::from_be_bytes([off[0], off[1], off[2], off[3]]) as usize)
+        .and_then(|rec0| data.get(rec0 + 36..rec0 + 40))
+        .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]));
+    match version {
+        Some(8) => AZW3,
+        _ => MOBI,
+    }
+}
+
+/// Finds the local name of the root element without parsing the whole document.
+fn xml_root(data: &[u8]) -> Option<String> {
+    let head = &data[..data.len().min(4096)];
+    let head = head.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(head);
+    let mut rest = std::str::from_utf8(head)
+        .or_else(|e| std::str::from_utf8(&head[..e.valid_up_to()]))
+        .ok()?;
+    loop {
+        rest = rest.trim_start();
+        let end = if rest.starts_with("<?") {
+            rest.find("?>")? + 2
+        } else if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        } else if rest.starts_with("<!") {
+            rest.find('>')? + 1
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let name = tag
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()?;
+            return Some(name.rsplit(':').next()?.to_string());
+        } else {
+            return None;
+        };
+        rest = &rest[end..];
+    }
+}
+
+/// Which file extensions are fine for a detected type - they're all the same container.
+pub fn extensions(detected: Detected) -> &'static [&'static str] {
+    match detected.name {
+        "epub" => &["epub", "kepub"],
+        "mobi" | "azw3" => &["mobi", "azw", "azw3", "prc", "kf8"],
+        "cbz" => &["cbz"],
+        "djvu" => &["djvu", "djv"],
+        "fb2" => &["fb2"],
+        _ => &[],
+    }
+}
+
+/// Returns `true` if the client named the file as something it clearly isn't,
+/// extensions we know nothing about never count as a mismatch.
+pub fn mismatch(detected: Detected, extension: &str) -> bool {
+    let known = [EPUB, PDF, MOBI, AZW3, FB2, CBZ, CBR, DJVU]
+        .iter()
+        .any(|d| d.name == extension || extensions(*d).contains(&extension));
+    known && detected.name != extension && !extensions(detected).contains(&extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// A palm database header with one record, its mobi header claiming `version`.
+    fn mobi(version: u32) -> Vec<u8> {
+        let mut data = vec![0; 200];
+        data[60..68].copy_from_slice(b"BOOKMOBI");
+        data[78..82].copy_from_slice(&100u32.to_be_bytes());
+        data[136..140].copy_from_slice(&version.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn magic_bytes() {
+        assert_eq!(detect(b"%PDF-1.7\n"), Some(PDF));
+        assert_eq!(detect(b"AT&TFORM\0\0\0\0DJVM"), Some(DJVU));
+        assert_eq!(detect(b"Rar!\x1a\x07\x01\x00"), Some(CBR));
+        assert_eq!(detect(b"just some text"), None);
+        assert_eq!(detect(b""), None);
+    }
+
+    #[test]
+    fn mobi_version_decides_azw3() {
+        assert_eq!(detect(&mobi(6)), Some(MOBI));
+        assert_eq!(detect(&mobi(8)), Some(AZW3));
+        // a record list pointing nowhere is still a mobi
+        let mut data = mobi(8);
+        data.truncate(120);
+        assert_eq!(detect(&data), Some(MOBI));
+    }
+
+    #[test]
+    fn fb2_root_element() {
+        let fb2 = "\u{feff}<?xml version=\"1.0\"?>\n<!-- a comment -->\n\
+            <FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\"></FictionBook>";
+        assert_eq!(detect(fb2.as_bytes()), Some(FB2));
+        assert_eq!(detect(b"<fb:FictionBook></fb:FictionBook>"), Some(FB2));
+        assert_eq!(detect(b"<?xml version=\"1.0\"?><html></html>"), None);
+    }
+
+    #[test]
+    fn epub_mimetype_entry() {
+        let epub = zip(&[
+            ("mimetype", b"application/epub+zip"),
+            ("META-INF/container.xml", b"<container/>"),
+        ]);
+        assert_eq!(detect(&epub), Some(EPUB));
+        // the mimetype entry is supposed to be first but isn't always
+        let epub = zip(&[
+            ("META-INF/container.xml", b"<container/>"),
+            ("mimetype", b"application/epub+zip\n"),
+        ]);
+        assert_eq!(detect(&epub), Some(EPUB));
+    }
+
+    #[test]
+    fn zip_of_images_is_a_comic() {
+        let cbz = zip(&[
+            ("001.JPG", b"\xff\xd8\xff"),
+            ("002.png", b"\x89PNG"),
+            ("ComicInfo.xml", b"<ComicInfo/>"),
+            ("__MACOSX/._001.JPG", b""),
+        ]);
+        assert_eq!(detect(&cbz), Some(CBZ));
+        // a mimetype that isn't an epub one is just another file that isn't a picture
+        let cbz = zip(&[("mimetype", b"application/zip"), ("1.jpg", b"")]);
+        assert_eq!(detect(&cbz), None);
+        let cbz = zip(&[("1.jpg", b""), ("2.webp", b"")]);
+        assert_eq!(detect(&cbz), Some(CBZ));
+    }
+
+    #[test]
+    fn other_zips_are_unknown() {
+        assert_eq!(detect(&zip(&[("notes.txt", b"hi"), ("1.jpg", b"")])), None);
+        assert_eq!(detect(&zip(&[])), None);
+        assert_eq!(detect(b"PK\x03\x04 not really a zip"), None);
+    }
+
+    #[test]
+    fn mismatched_extensions() {
+        assert!(mismatch(EPUB, "pdf"));
+        assert!(mismatch(PDF, "epub"));
+        assert!(!mismatch(EPUB, "epub"));
+        assert!(!mismatch(EPUB, "kepub"));
+        assert!(!mismatch(AZW3, "mobi"));
+        // we don't know what a txt is, so we can't say it's wrong
+        assert!(!mismatch(PDF, "txt"));
+    }
+}
@@ -0,0 +1,84 @@
+use super::{clean, identifier, Metadata, MetadataExtractor};
+use epub::doc::EpubDoc;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::Cursor;
+
+pub struct Epub;
+
+impl MetadataExtractor for Epub {
+    fn extract(&self, data: &[u8]) -> Option<Metadata> {
+        // maybe it wasnt an epub (?) lol
+        let mut epub = EpubDoc::from_reader(Cursor::new(data.to_vec())).ok()?;
+        let all = |name: &str| -> Vec<String> {
+            epub.metadata
+                .get(name)
+                .into_iter()
+                .flatten()
+                .filter_map(|v| clean(v))
+                .collect()
+        };
+        let first = |name: &str| all(name).into_iter().next();
+
+        // calibre writes its own meta tags, epub3 uses belongs-to-collection
+        let (series, series_index) = match first("calibre:series") {
+            Some(series) => (Some(series), first("calibre:series_index")),
+            None => (first("belongs-to-collection"), first("group-position")),
+        };
+        let mut meta = Metadata {
+            title: first("title"),
+            creators: all("creator"),
+            language: first("language"),
+            publisher: first("publisher"),
+            description: first("description"),
+            published: first("date"),
+            // the epub crate doesn't keep the `opf:scheme` attribute so we have to guess
+            identifiers: all("identifier").into_iter().map(identifier).collect(),
+            subjects: all("subject"),
+            series,
+            series_index: series_index.and_then(|i| i.parse().ok()),
+            cover: None,
+        };
+        meta.cover = epub.get_cover();
+        Some(meta)
+    }
+}
+
+/// Pulls the readable text out of an epub in reading order, for the search index.
+pub fn text(data: &[u8]) -> Option<String> {
+    let mut epub = EpubDoc::from_reader(Cursor::new(data.to_vec())).ok()?;
+    let mut out = String::new();
+    for id in epub.spine.clone() {
+        if let Some((chapter, _mime)) = epub.get_resource_str(&id) {
+            strip_tags(&chapter, &mut out);
+        }
+    }
+    (!out.is_empty()).then_some(out)
+}
+
+/// Appends the text of an xhtml chapter to `out`, whatever parses before a broken tag is kept.
+fn strip_tags(xhtml: &str, out: &mut String) {
+    let mut reader = Reader::from_str(xhtml);
+    reader.trim_text(true);
+    let mut skip = 0;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if matches!(e.local_name().as_ref(), b"script" | b"style") => {
+                skip += 1
+            }
+            Ok(Event::End(e)) if matches!(e.local_name().as_ref(), b"script" | b"style") => {
+                skip -= 1
+            }
+            Ok(Event::Text(e)) if skip == 0 => {
+                // html entities like &nbsp; aren't xml, keep those raw rather than losing the text
+                match e.unescape() {
+                    Ok(text) => out.push_str(&text),
+                    Err(_) => out.push_str(&String::from_utf8_lossy(&e)),
+                }
+                out.push(' ');
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::{clean, identifier, image_mime, walk_xml, Metadata, MetadataExtractor};
+use base64::Engine;
+
+pub struct Fb2;
+
+impl MetadataExtractor for Fb2 {
+    fn extract(&self, data: &[u8]) -> Option<Metadata> {
+        let mut meta = Metadata::default();
+        let mut author: Vec<String> = vec![];
+        let mut annotation: Vec<String> = vec![];
+        let mut cover_id: Option<String> = None;
+        let mut is_fb2 = false;
+
+        walk_xml(data, |path, attrs, text| {
+            let path: Vec<&str> = path.iter().map(String::as_str).collect();
+            match path.as_slice() {
+                ["FictionBook"] => is_fb2 = true,
+                ["FictionBook", "description", "title-info", rest @ ..] => match rest {
+                    ["book-title"] => meta.title = clean(text),
+                    ["author", "first-name" | "middle-name" | "last-name"] => {
+                        author.extend(clean(text))
+                    }
+                    // only use the nickname if there's no real name
+                    ["author", "nickname"] if author.is_empty() => author.extend(clean(text)),
+                    ["author"] => {
+                        if !author.is_empty() {
+                            meta.creators.push(author.join(" "));
+                        }
+                        author.clear();
+                    }
+                    ["annotation", .., _] => annotation.extend(clean(text)),
+                    ["annotation"] => {
+                        meta.description = clean(&annotation.join("\n\n"));
+                    }
+                    ["genre"] => meta.subjects.extend(clean(text)),
+                    ["keywords"] => meta.subjects.extend(text.split(',').filter_map(clean)),
+                    ["date"] => {
+                        meta.published = attrs.get("value").and_then(|v| clean(v)).or(clean(text))
+                    }
+                    ["lang"] => meta.language = clean(text),
+                    ["sequence"] if meta.series.is_none() => {
+                        meta.series = attrs.get("name").and_then(|n| clean(n));
+                        meta.series_index = attrs.get("number").and_then(|n| n.parse().ok());
+                    }
+                    ["coverpage", "image"] => {
+                        cover_id = attrs
+                            .get("href")
+                            .map(|href| href.trim_start_matches('#').to_string())
+                    }
+                    _ => {}
+                },
+                ["FictionBook", "description", "publish-info", field] => match *field {
+                    "publisher" => meta.publisher = clean(text),
+                    "isbn" => meta.identifiers.extend(clean(text).map(|isbn| {
+                        let mut id = identifier(isbn);
+                        id.scheme = Some("isbn".to_string());
+                        id
+                    })),
+                    "year" if meta.published.is_none() => meta.published = clean(text),
+                    _ => {}
+                },
+                ["FictionBook", "description", "document-info", "id"] => {
+                    meta.identifiers.extend(clean(text).map(identifier))
+                }
+                ["FictionBook", "binary"]
+                    if cover_id.is_some() && attrs.get("id") == cover_id.as_ref() =>
+                {
+                    let b64: String = text.split_whitespace().collect();
+                    if let Ok(image) = base64::engine::general_purpose::STANDARD.decode(b64) {
+                        let mime = attrs
+                            .get("content-type")
+                            .cloned()
+                            .or(image_mime(&image).map(str::to_string));
+                        meta.cover = mime.map(|mime| (image, mime));
+                    }
+                }
+                _ => {}
+            }
+        })?;
+        is_fb2.then_some(meta)
+    }
+}
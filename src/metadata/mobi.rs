@@ -0,0 +1,98 @@
+use super::{clean, identifier, image_mime, Metadata, MetadataExtractor};
+
+pub struct Mobi;
+
+fn be16(data: &[u8], at: usize) -> Option<usize> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?) as usize)
+}
+
+fn be32(data: &[u8], at: usize) -> Option<usize> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize)
+}
+
+// Layout reference: https://wiki.mobileread.com/wiki/MOBI
+// PalmDB header -> record list -> record 0 (PalmDOC header, MOBI header, EXTH header)
+impl MetadataExtractor for Mobi {
+    fn extract(&self, data: &[u8]) -> Option<Metadata> {
+        // azw3 is the same container, just with a newer mobi header version
+        if data.get(60..68)? != b"BOOKMOBI" {
+            return None;
+        }
+        let records: Vec<usize> = (0..be16(data, 76)?)
+            .map(|i| be32(data, 78 + i * 8))
+            .collect::<Option<_>>()?;
+        let record = |i: usize| -> Option<&[u8]> {
+            let start = *records.get(i)?;
+            let end = records.get(i + 1).copied().unwrap_or(data.len());
+            data.get(start..end)
+        };
+
+        let rec0 = record(0)?;
+        if rec0.get(16..20)? != b"MOBI" {
+            return None;
+        }
+        let header_len = be32(rec0, 20)?;
+        let utf8 = be32(rec0, 28)? == 65001;
+        let decode = |bytes: &[u8]| -> Option<String> {
+            let text = if utf8 {
+                String::from_utf8_lossy(bytes).to_string()
+            } else {
+                // cp1252, close enough
+                bytes.iter().map(|&b| b as char).collect()
+            };
+            clean(&text)
+        };
+        let full_name = (be32(rec0, 84)?, be32(rec0, 88)?);
+        let first_image = be32(rec0, 108)?;
+        let has_exth = be32(rec0, 128)? & 0x40 != 0;
+
+        let mut meta = Metadata {
+            title: rec0
+                .get(full_name.0..full_name.0 + full_name.1)
+                .and_then(decode),
+            ..Default::default()
+        };
+        let mut cover_offset = None;
+        let exth = 16 + header_len;
+        if has_exth && rec0.get(exth..exth + 4) == Some(b"EXTH") {
+            let mut pos = exth + 12;
+            for _ in 0..be32(rec0, exth + 8)? {
+                let (kind, len) = (be32(rec0, pos)?, be32(rec0, pos + 4)?);
+                if len < 8 {
+                    break;
+                }
+                let value = rec0.get(pos + 8..pos + len)?;
+                match kind {
+                    100 => meta.creators.extend(decode(value)),
+                    101 => meta.publisher = decode(value),
+                    103 => meta.description = decode(value),
+                    104 => meta.identifiers.extend(decode(value).map(|isbn| {
+                        let mut id = identifier(isbn);
+                        id.scheme = Some("isbn".to_string());
+                        id
+                    })),
+                    105 => meta.subjects.extend(decode(value)),
+                    106 => meta.published = decode(value),
+                    113 => meta.identifiers.extend(decode(value).map(|asin| {
+                        let mut id = identifier(asin);
+                        id.scheme = Some("asin".to_string());
+                        id
+                    })),
+                    201 => cover_offset = be32(value, 0),
+                    503 => meta.title = decode(value).or(meta.title),
+                    524 => meta.language = decode(value),
+                    _ => {}
+                }
+                pos += len;
+            }
+        }
+
+        // 0xFFFFFFFF means there are no images in the book
+        if let (Some(offset), true) = (cover_offset, first_image != u32::MAX as usize) {
+            if let Some(image) = record(first_image + offset) {
+                meta.cover = image_mime(image).map(|mime| (image.to_vec(), mime.to_string()));
+            }
+        }
+        Some(meta)
+    }
+}
@@ -0,0 +1,60 @@
+use super::{clean, Metadata, MetadataExtractor};
+use lopdf::{Dictionary, Document, Object};
+
+pub struct Pdf;
+
+/// Decodes a pdf text string, either UTF-16BE with a BOM or (close enough to) PDFDocEncoding.
+fn text(obj: &Object) -> Option<String> {
+    let bytes = obj.as_str().ok()?;
+    let text = match bytes {
+        [0xFE, 0xFF, rest @ ..] => String::from_utf16_lossy(
+            &rest
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<u16>>(),
+        ),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    };
+    clean(&text)
+}
+
+/// `D:20200501123000+02'00'` -> `2020-05-01`
+fn date(value: &str) -> Option<String> {
+    let digits = value.strip_prefix("D:").unwrap_or(value);
+    match digits.get(..8) {
+        Some(d) if d.chars().all(|c| c.is_ascii_digit()) => {
+            Some(format!("{}-{}-{}", &d[..4], &d[4..6], &d[6..8]))
+        }
+        _ => digits.get(..4).map(str::to_string),
+    }
+}
+
+impl MetadataExtractor for Pdf {
+    fn extract(&self, data: &[u8]) -> Option<Metadata> {
+        let doc = Document::load_mem(data).ok()?;
+        let info: Option<&Dictionary> = doc.trailer.get(b"Info").ok().and_then(|info| match info {
+            Object::Reference(id) => doc.get_dictionary(*id).ok(),
+            obj => obj.as_dict().ok(),
+        });
+        // no info dictionary is still a valid pdf, just not a very helpful one
+        let Some(info) = info else {
+            return Some(Metadata::default());
+        };
+        let get = |key: &[u8]| info.get(key).ok().and_then(text);
+
+        Some(Metadata {
+            title: get(b"Title"),
+            creators: get(b"Author")
+                .map(|a| a.split(['&', ';']).filter_map(clean).collect())
+                .unwrap_or_default(),
+            description: get(b"Subject"),
+            published: get(b"CreationDate").and_then(|d| date(&d)),
+            subjects: get(b"Keywords")
+                .map(|k| k.split([',', ';']).filter_map(clean).collect())
+                .unwrap_or_default(),
+            // we'd have to render the first page to get a cover
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,352 @@
+use crate::config::Config;
+use crate::metadata::epub;
+use crate::storage;
+use entity::book_info::Model as BIModel;
+use entity::prelude::{Book, BookInfo, BookOverride, FileType};
+use log::{info, warn};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, FAST, INDEXED, STORED,
+};
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer,
+};
+use tantivy::{
+    Index, IndexReader, IndexWriter, ReloadPolicy, SnippetGenerator, TantivyDocument, Term,
+};
+
+const TOKENIZER: &str = "stoka";
+const WRITER_MEMORY: usize = 30_000_000;
+const SNIPPET_CHARS: usize = 200;
+
+#[derive(Clone, Copy)]
+struct Fields {
+    book_id: Field,
+    user_id: Field,
+    title: Field,
+    creator: Field,
+    series: Field,
+    publisher: Field,
+    subjects: Field,
+    description: Field,
+    content: Field,
+}
+
+impl Fields {
+    /// Text fields we search and make snippets for, with how much a match in them counts.
+    fn text(&self) -> [(&'static str, Field, f32); 7] {
+        [
+            ("title", self.title, 3.0),
+            ("creator", self.creator, 2.0),
+            ("series", self.series, 1.5),
+            ("subjects", self.subjects, 1.0),
+            ("publisher", self.publisher, 0.5),
+            ("description", self.description, 1.0),
+            ("content", self.content, 0.5),
+        ]
+    }
+}
+
+/// One book matching a search, `snippets` maps field names to highlighted html fragments.
+pub struct Hit {
+    pub book_id: i32,
+    pub score: f32,
+    pub snippets: BTreeMap<&'static str, String>,
+}
+
+/// Embedded full text index of everyone's books, lives on disk next to the blobs so it works
+/// the same whatever database we are running on.
+pub struct Search {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+    index_content: bool,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let text = TextOptions::default()
+        .set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        )
+        .set_stored();
+    let fields = Fields {
+        book_id: builder.add_u64_field("book_id", INDEXED | STORED | FAST),
+        user_id: builder.add_u64_field("user_id", INDEXED),
+        title: builder.add_text_field("title", text.clone()),
+        creator: builder.add_text_field("creator", text.clone()),
+        series: builder.add_text_field("series", text.clone()),
+        publisher: builder.add_text_field("publisher", text.clone()),
+        subjects: builder.add_text_field("subjects", text.clone()),
+        description: builder.add_text_field("description", text.clone()),
+        content: builder.add_text_field("content", text),
+    };
+    (builder.build(), fields)
+}
+
+impl Search {
+    pub fn open(config: &Config) -> tantivy::Result<Search> {
+        let path = config
+            .search
+            .index_path
+            .clone()
+            .unwrap_or_else(|| Path::new(&config.filepath).join("search-index"));
+        // the index is plain text on disk, whole books in it would undo the encryption
+        if config.search.index_content && config.encryption.is_some() {
+            return Err(tantivy::TantivyError::InvalidArgument(
+                "search.index_content can't be used together with encryption".to_string(),
+            ));
+        }
+        std::fs::create_dir_all(&path)?;
+        let (schema, fields) = schema();
+        let index = Index::open_or_create(MmapDirectory::open(&path)?, schema)?;
+        // folding accents lets `bronte` find `Brontë`
+        index.tokenizers().register(
+            TOKENIZER,
+            TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter)
+                .build(),
+        );
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        Ok(Search {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+            index_content: config.search.index_content,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.searcher().num_docs() == 0
+    }
+
+    /// Whether callers should bother extracting the text of books for [`Search::index`].
+    pub fn indexes_content(&self) -> bool {
+        self.index_content
+    }
+
+    fn document(
+        &self,
+        book_id: i32,
+        user_id: i32,
+        title: &str,
+        meta: Option<&BIModel>,
+        content: Option<String>,
+    ) -> TantivyDocument {
+        let f = self.fields;
+        let mut doc = TantivyDocument::new();
+        doc.add_u64(f.book_id, book_id as u64);
+        doc.add_u64(f.user_id, user_id as u64);
+        // the file name is often all we have, keep it even when the metadata has a proper title
+        doc.add_text(f.title, title);
+        if let Some(meta) = meta {
+            if meta.title != title {
+                doc.add_text(f.title, &meta.title);
+            }
+            doc.add_text(f.creator, &meta.creator);
+            for (field, value) in [
+                (f.series, &meta.series),
+                (f.publisher, &meta.publisher),
+                (f.description, &meta.description),
+            ] {
+                if let Some(value) = value {
+                    doc.add_text(field, value);
+                }
+            }
+            for subject in meta.subjects.iter().flat_map(|s| &s.0) {
+                doc.add_text(f.subjects, subject);
+            }
+        }
+        if let Some(content) = content.filter(|_| self.index_content) {
+            doc.add_text(f.content, content);
+        }
+        doc
+    }
+
+    /// Text we indexed for a book before, so metadata edits don't need the file again.
+    fn stored_content(&self, book_id: i32) -> tantivy::Result<Option<String>> {
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(
+            Term::from_field_u64(self.fields.book_id, book_id as u64),
+            IndexRecordOption::Basic,
+        );
+        let Some((_, address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop() else {
+            return Ok(None);
+        };
+        let doc: TantivyDocument = searcher.doc(address)?;
+        Ok(doc
+            .get_first(self.fields.content)
+            .and_then(|v| v.as_str())
+            .map(str::to_string))
+    }
+
+    fn commit(&self, writer: &mut IndexWriter) -> tantivy::Result<()> {
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    /// Adds a book or replaces what we had of it, `content: None` keeps the text indexed before.
+    pub fn index(
+        &self,
+        book_id: i32,
+        user_id: i32,
+        title: &str,
+        meta: Option<&BIModel>,
+        content: Option<String>,
+    ) -> tantivy::Result<()> {
+        let content = match content {
+            Some(content) => Some(content),
+            None if self.index_content => self.stored_content(book_id)?,
+            None => None,
+        };
+        let doc = self.document(book_id, user_id, title, meta, content);
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_u64(self.fields.book_id, book_id as u64));
+        writer.add_document(doc)?;
+        self.commit(&mut writer)
+    }
+
+    pub fn remove(&self, book_id: i32) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_u64(self.fields.book_id, book_id as u64));
+        self.commit(&mut writer)
+    }
+
+    /// Ranked search over one user's books, anything the query parser doesn't understand is
+    /// searched for as plain words instead of failing.
+    pub fn search(
+        &self,
+        user_id: i32,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> tantivy::Result<Vec<Hit>> {
+        let fields = self.fields.text();
+        let mut parser =
+            QueryParser::for_index(&self.index, fields.iter().map(|(_, f, _)| *f).collect());
+        for (_, field, boost) in fields {
+            parser.set_field_boost(field, boost);
+        }
+        let (text, _errors) = parser.parse_query_lenient(query);
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, text),
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_u64(self.fields.user_id, user_id as u64),
+                    IndexRecordOption::Basic,
+                )) as Box<dyn Query>,
+            ),
+        ]);
+
+        let searcher = self.reader.searcher();
+        let top = searcher.search(&query, &TopDocs::with_limit(limit).and_offset(offset))?;
+        let mut snippets = vec![];
+        for (name, field, _) in fields {
+            let mut generator = SnippetGenerator::create(&searcher, &query, field)?;
+            generator.set_max_num_chars(SNIPPET_CHARS);
+            snippets.push((name, generator));
+        }
+
+        let mut hits = vec![];
+        for (score, address) in top {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let Some(book_id) = doc.get_first(self.fields.book_id).and_then(|v| v.as_u64()) else {
+                continue;
+            };
+            hits.push(Hit {
+                book_id: book_id as i32,
+                score,
+                snippets: snippets
+                    .iter()
+                    .map(|(name, generator)| (*name, generator.snippet_from_doc(&doc)))
+                    .filter(|(_, snippet)| !snippet.is_empty())
+                    .map(|(name, snippet)| (name, snippet.to_html()))
+                    .collect(),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Text of a stored book for the index, only epubs for now.
+pub fn content(config: &Config, file_type: &str, hash: &str) -> Option<String> {
+    if file_type != "epub" {
+        return None;
+    }
+    match storage::read(config, &format!("{}.bin", hash)) {
+        Ok(data) => epub::text(&data),
+        Err(e) => {
+            warn!("Couldn't read {} for indexing: {}", hash, e);
+            None
+        }
+    }
+}
+
+/// Throws the index away and indexes every book in the database again.
+pub async fn rebuild(
+    search: &Search,
+    config: &Config,
+    db: &DatabaseConnection,
+) -> Result<usize, DbErr> {
+    let books = Book::find().all(db).await?;
+    let file_types: HashMap<i32, String> = FileType::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|ft| (ft.id, ft.name))
+        .collect();
+    let infos: HashMap<String, BIModel> = BookInfo::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bi| (bi.book_hash.clone(), bi))
+        .collect();
+    let mut edits: HashMap<i32, _> = BookOverride::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bo| (bo.book_id, bo))
+        .collect();
+
+    let index_error = |e: tantivy::TantivyError| DbErr::Custom(e.to_string());
+    let mut writer = search.writer.lock().unwrap();
+    writer.delete_all_documents().map_err(index_error)?;
+    for book in &books {
+        let edits = edits.remove(&book.id);
+        let title = edits
+            .as_ref()
+            .and_then(|e| e.title.as_deref())
+            .unwrap_or(&book.title)
+            .to_string();
+        let meta = infos
+            .get(&book.hash)
+            .cloned()
+            .map(|bi| crate::api::book::merge(bi, edits));
+        let content = match (search.index_content, file_types.get(&book.file_tyoe)) {
+            (true, Some(ft)) => content(config, ft, &book.hash),
+            _ => None,
+        };
+        let doc = search.document(book.id, book.user_id, &title, meta.as_ref(), content);
+        writer.add_document(doc).map_err(index_error)?;
+    }
+    search.commit(&mut writer).map_err(index_error)?;
+    info!("Indexed {} books.", books.len());
+    Ok(books.len())
+}
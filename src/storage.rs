@@ -0,0 +1,307 @@
+use crate::config::Config;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use log::{info, warn};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Encrypted blobs look like this:
+// MAGIC | VERSION | key nonce (12) | wrapped data key (32 + 16) | data nonce (12) | ciphertext
+// The data key is random per blob and wrapped with the server key from the config,
+// the blob name is used as associated data so blobs can't be swapped around on disk.
+const MAGIC: &[u8; 4] = b"STKE";
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const WRAPPED_KEY_LEN: usize = 32 + 16;
+const HEADER_LEN: usize = MAGIC.len() + 1 + NONCE_LEN + WRAPPED_KEY_LEN + NONCE_LEN;
+const KEY_AD: &[u8] = b"stoka-data-key";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub fn path(config: &Config, name: &str) -> PathBuf {
+    Path::new(&config.filepath).join(name)
+}
+
+/// Writes `data` to a temporary file next to `dest` and moves it into place,
+/// so a half written blob never shows up under its real name.
+/// Each call gets its own temporary file, concurrent writers of the same blob don't clobber each other.
+fn replace(config: &Config, dest: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path(config, &format!(".{:016x}.tmp", OsRng.next_u64()));
+    let res = std::fs::File::create(&tmp)
+        .and_then(|mut f| f.write_all(data))
+        .and_then(|()| std::fs::rename(&tmp, dest));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// Reads the server key the config points to, so blobs don't go to disk for it every time.
+pub fn load_key(config: &mut Config) -> io::Result<()> {
+    let Some(enc) = &mut config.encryption else {
+        return Ok(());
+    };
+    let raw = std::fs::read(&enc.key)?;
+    let key = match raw.len() {
+        32 => raw,
+        _ => hex::decode(String::from_utf8_lossy(&raw).trim())
+            .map_err(|_| invalid("encryption key is neither 32 raw bytes nor hex"))?,
+    };
+    if key.len() != 32 {
+        return Err(invalid("encryption key has to be 32 bytes long"));
+    }
+    enc.server_key = Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+    Ok(())
+}
+
+fn server_key(config: &Config) -> io::Result<Option<&Aes256Gcm>> {
+    match &config.encryption {
+        Some(enc) => match &enc.server_key {
+            Some(key) => Ok(Some(key)),
+            None => Err(invalid("encryption key was never loaded")),
+        },
+        None => Ok(None),
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && &data[..MAGIC.len()] == MAGIC && data[MAGIC.len()] == VERSION
+}
+
+fn encrypt(server: &Aes256Gcm, name: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let data_key = Aes256Gcm::generate_key(OsRng);
+    let key_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let wrapped = server
+        .encrypt(
+            &key_nonce,
+            Payload {
+                msg: &data_key,
+                aad: KEY_AD,
+            },
+        )
+        .map_err(|_| invalid("failed to wrap data key"))?;
+    let data_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(&data_key)
+        .encrypt(
+            &data_nonce,
+            Payload {
+                msg: data,
+                aad: name.as_bytes(),
+            },
+        )
+        .map_err(|_| invalid("failed to encrypt blob"))?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&key_nonce);
+    out.extend_from_slice(&wrapped);
+    out.extend_from_slice(&data_nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn decrypt(server: &Aes256Gcm, name: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut pos = MAGIC.len() + 1;
+    let key_nonce = Nonce::from_slice(&data[pos..pos + NONCE_LEN]);
+    pos += NONCE_LEN;
+    let wrapped = &data[pos..pos + WRAPPED_KEY_LEN];
+    pos += WRAPPED_KEY_LEN;
+    let data_nonce = Nonce::from_slice(&data[pos..pos + NONCE_LEN]);
+    pos += NONCE_LEN;
+
+    let data_key = server
+        .decrypt(
+            key_nonce,
+            Payload {
+                msg: wrapped,
+                aad: KEY_AD,
+            },
+        )
+        .map_err(|_| invalid("failed to unwrap data key - wrong server key?"))?;
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key))
+        .decrypt(
+            data_nonce,
+            Payload {
+                msg: &data[pos..],
+                aad: name.as_bytes(),
+            },
+        )
+        .map_err(|_| invalid("failed to decrypt blob"))
+}
+
+/// Reads a blob, decrypting it if it was stored encrypted.
+/// Plaintext blobs are still readable so a store can be encrypted after the fact.
+pub fn read(config: &Config, name: &str) -> io::Result<Vec<u8>> {
+    let data = std::fs::read(path(config, name))?;
+    if !is_encrypted(&data) {
+        return Ok(data);
+    }
+    match server_key(config)? {
+        Some(server) => decrypt(server, name, &data),
+        None => Err(invalid(
+            "blob is encrypted but no encryption key is configured",
+        )),
+    }
+}
+
+/// Writes a blob, encrypting it when encryption is enabled in the config.
+pub fn write(config: &Config, name: &str, data: &[u8]) -> io::Result<()> {
+    let data = match server_key(config)? {
+        Some(server) => encrypt(server, name, data)?,
+        None => data.to_vec(),
+    };
+    replace(config, &path(config, name), &data)
+}
+
+pub fn exists(config: &Config, name: &str) -> bool {
+    path(config, name).exists()
+}
+
+/// Removes a blob, it not being there in the first place is fine too.
+pub fn remove(config: &Config, name: &str) -> io::Result<()> {
+    match std::fs::remove_file(path(config, name)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Size of a blob as it was written, without the encryption overhead.
+pub fn size(config: &Config, name: &str) -> io::Result<u64> {
+    let len = std::fs::metadata(path(config, name))?.len();
+    Ok(match needs_decryption(config, name)? {
+        // the data is followed by a 16 byte tag
+        true => len
+            .checked_sub(HEADER_LEN as u64 + 16)
+            .ok_or_else(|| invalid("encrypted blob is cut short"))?,
+        false => len,
+    })
+}
+
+/// Returns `true` if the blob has to go through [`read`] instead of being served straight from disk.
+pub fn needs_decryption(config: &Config, name: &str) -> io::Result<bool> {
+    let mut header = [0u8; HEADER_LEN];
+    let mut file = std::fs::File::open(path(config, name))?;
+    match io::Read::read_exact(&mut file, &mut header) {
+        Ok(()) => Ok(is_encrypted(&header)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Encrypts every plaintext blob in `config.filepath` in place.
+pub fn encrypt_store(config: &Config) -> io::Result<()> {
+    let Some(server) = server_key(config)? else {
+        return Err(io::Error::other(
+            "encryption is not configured, add an `encryption` section to config.json",
+        ));
+    };
+    let (mut encrypted, mut skipped) = (0, 0);
+    for entry in std::fs::read_dir(&config.filepath)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.ends_with(".bin") || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let data = std::fs::read(entry.path())?;
+        if is_encrypted(&data) {
+            skipped += 1;
+            continue;
+        }
+        replace(config, &entry.path(), &encrypt(server, &name, &data)?)?;
+        encrypted += 1;
+    }
+    if encrypted == 0 && skipped == 0 {
+        warn!("No blobs found in {}", config.filepath);
+    }
+    info!("Encrypted {encrypted} blobs, {skipped} were already encrypted.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncryptionConfig;
+    use crate::testing;
+
+    fn encrypt_with_key(config: &mut Config, hex_key: bool) {
+        let key = path(config, "server.key");
+        match hex_key {
+            true => std::fs::write(&key, hex::encode([7u8; 32])).unwrap(),
+            false => std::fs::write(&key, [7u8; 32]).unwrap(),
+        }
+        config.encryption = Some(EncryptionConfig {
+            key,
+            server_key: None,
+        });
+        load_key(config).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut config, _dir) = testing::config();
+        encrypt_with_key(&mut config, true);
+        write(&config, "book.bin", b"some book").unwrap();
+        let raw = std::fs::read(path(&config, "book.bin")).unwrap();
+        assert!(is_encrypted(&raw));
+        assert!(!raw.windows(9).any(|w| w == b"some book"));
+        assert!(needs_decryption(&config, "book.bin").unwrap());
+        assert_eq!(size(&config, "book.bin").unwrap(), 9);
+        assert_eq!(read(&config, "book.bin").unwrap(), b"some book");
+    }
+
+    #[test]
+    fn renamed_blob_fails() {
+        let (mut config, _dir) = testing::config();
+        encrypt_with_key(&mut config, false);
+        write(&config, "a.bin", b"some book").unwrap();
+        std::fs::rename(path(&config, "a.bin"), path(&config, "b.bin")).unwrap();
+        assert!(read(&config, "b.bin").is_err());
+    }
+
+    #[test]
+    fn damaged_blob_fails() {
+        let (mut config, _dir) = testing::config();
+        encrypt_with_key(&mut config, false);
+        write(&config, "book.bin", b"some book").unwrap();
+        let raw = std::fs::read(path(&config, "book.bin")).unwrap();
+
+        std::fs::write(path(&config, "book.bin"), &raw[..raw.len() - 1]).unwrap();
+        assert!(read(&config, "book.bin").is_err());
+
+        let mut tampered = raw.clone();
+        tampered[HEADER_LEN] ^= 1;
+        std::fs::write(path(&config, "book.bin"), &tampered).unwrap();
+        assert!(read(&config, "book.bin").is_err());
+
+        // a header and nothing else isn't even long enough for the tag
+        std::fs::write(path(&config, "book.bin"), &raw[..HEADER_LEN]).unwrap();
+        assert!(read(&config, "book.bin").is_err());
+        assert!(size(&config, "book.bin").is_err());
+    }
+
+    #[test]
+    fn plaintext_blobs_stay_readable() {
+        let (mut config, _dir) = testing::config();
+        write(&config, "old.bin", b"from before encryption").unwrap();
+        encrypt_with_key(&mut config, false);
+        assert!(!needs_decryption(&config, "old.bin").unwrap());
+        assert_eq!(read(&config, "old.bin").unwrap(), b"from before encryption");
+
+        encrypt_store(&config).unwrap();
+        assert!(needs_decryption(&config, "old.bin").unwrap());
+        assert_eq!(read(&config, "old.bin").unwrap(), b"from before encryption");
+    }
+
+    #[test]
+    fn encrypted_blob_needs_the_key() {
+        let (mut config, _dir) = testing::config();
+        encrypt_with_key(&mut config, false);
+        write(&config, "book.bin", b"some book").unwrap();
+        config.encryption = None;
+        assert!(read(&config, "book.bin").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Bits the tests share.
+use crate::config::{Config, SmtpConfig, SmtpSecurity};
+use crate::search::Search;
+use crate::AuthData;
+use actix_http::Request;
+use actix_web::body::BoxBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::{test, web, App, HttpMessage};
+use entity::user;
+use migration::MigratorTrait;
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DatabaseConnection};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+
+/// A config keeping its blobs in a fresh directory, which goes away with the returned `TempDir`,
+/// and signing tokens with a key pair that is only ever used here.
+pub fn config() -> (Config, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let config = serde_json::from_value(serde_json::json!({
+        "address": "127.0.0.1",
+        "filepath": dir.path(),
+        "port": 0,
+        "jwt": {
+            "valid_for": 3600,
+            "private_key": concat!(env!("CARGO_MANIFEST_DIR"), "/src/testdata/jwt.pem"),
+            "public_key": concat!(env!("CARGO_MANIFEST_DIR"), "/src/testdata/jwt.pub.pem"),
+        },
+        "db": { "connection_string": "sqlite::memory:", "connections": 1 },
+    }))
+    .unwrap();
+    (config, dir)
+}
+
+/// An empty, fully migrated database that lives as long as the connection.
+pub async fn db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    migration::Migrator::up(&db, None).await.unwrap();
+    db
+}
+
+pub async fn user(db: &DatabaseConnection, username: &str) -> user::Model {
+    user::ActiveModel {
+        id: ActiveValue::NotSet,
+        username: ActiveValue::Set(username.to_string()),
+        password: ActiveValue::Set(String::new()),
+        admin: ActiveValue::Set(false),
+        kosync_key: ActiveValue::Set(None),
+    }
+    .insert(db)
+    .await
+    .unwrap()
+}
+
+/// A search index in the config's blob directory.
+pub fn search(config: &Config) -> web::Data<Search> {
+    web::Data::new(Search::open(config).unwrap())
+}
+
+/// The routes from `configure` under `/api`, with `user` logged in without needing a token.
+pub async fn app(
+    config: &Config,
+    db: &DatabaseConnection,
+    search: &web::Data<Search>,
+    user: &user::Model,
+    configure: fn(&mut web::ServiceConfig),
+) -> impl Service<Request, Response = ServiceResponse<BoxBody>, Error = actix_web::Error> {
+    let user = user.clone();
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(db.clone()))
+            .app_data(search.clone())
+            .wrap_fn(move |req, srv| {
+                req.extensions_mut().insert(AuthData(user.clone()));
+                srv.call(req)
+            })
+            .service(web::scope("/api").configure(configure)),
+    )
+    .await
+}
+
+/// GETs `uri` and reads the json it answers with.
+pub async fn get_json(
+    app: &impl Service<Request, Response = ServiceResponse<BoxBody>, Error = actix_web::Error>,
+    uri: &str,
+) -> serde_json::Value {
+    test::call_and_read_body_json(app, test::TestRequest::get().uri(uri).to_request()).await
+}
+
+/// Mails the stand-in from [`smtp`] took, headers and all.
+pub type Outbox = Arc<Mutex<Vec<String>>>;
+
+/// Points `config` at a mail server on a free local port that takes every mail and keeps it in
+/// the returned outbox instead of sending it anywhere.
+pub fn smtp(config: &mut Config) -> Outbox {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    config.smtp = Some(SmtpConfig {
+        host: "127.0.0.1".to_string(),
+        port: Some(listener.local_addr().unwrap().port()),
+        security: SmtpSecurity::None,
+        username: None,
+        password: None,
+        from: "stoka@example.com".to_string(),
+    });
+    let outbox = Outbox::default();
+    let kept = outbox.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let kept = kept.clone();
+            thread::spawn(move || smtp_session(stream, &kept));
+        }
+    });
+    outbox
+}
+
+/// Just enough SMTP for lettre: everything is fine and whatever comes after DATA is a mail.
+fn smtp_session(stream: TcpStream, outbox: &Outbox) -> std::io::Result<()> {
+    let mut out = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+    out.write_all(b"220 localhost\r\n")?;
+    while let Some(line) = lines.next() {
+        let command = line?.to_uppercase();
+        if command.starts_with("DATA") {
+            out.write_all(b"354 go ahead\r\n")?;
+            let mut mail = String::new();
+            for line in lines.by_ref() {
+                let line = line?;
+                if line == "." {
+                    break;
+                }
+                mail.push_str(&line);
+                mail.push('\n');
+            }
+            outbox.lock().unwrap().push(mail);
+            out.write_all(b"250 sent\r\n")?;
+        } else if command.starts_with("QUIT") {
+            out.write_all(b"221 bye\r\n")?;
+            break;
+        } else {
+            out.write_all(b"250 ok\r\n")?;
+        }
+    }
+    Ok(())
+}